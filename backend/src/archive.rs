@@ -0,0 +1,446 @@
+//! backups: polls with their ballots, admin secrets and voter tokens written to a versioned
+//! archive, so they can be restored or moved to another server
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use common::{AdminSecret, Poll, PollV2, PublicPollId, PublicUserId, VoterToken};
+use serde::{Deserialize, Serialize};
+
+use crate::{store::PollStore, tally};
+
+/// bumped whenever `ArchiveRecord` changes incompatibly
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// an archive is a sequence of these, starting with a header
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum ArchiveRecord {
+    Header {
+        version: u32,
+    },
+    Poll {
+        poll: Poll,
+        admin_secret: AdminSecret,
+    },
+    /// someone who voted in one of the archived polls, so they can still change their ballot
+    Voter {
+        user_id: PublicUserId,
+        token: VoterToken,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ArchiveFormat {
+    /// one JSON object per line
+    JsonLines,
+    /// concatenated CBOR values
+    Cbor,
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(ArchiveFormat::JsonLines),
+            "cbor" => Ok(ArchiveFormat::Cbor),
+            _ => Err(format!(
+                "unknown archive format {s:?}, expected jsonl or cbor"
+            )),
+        }
+    }
+}
+
+/// what `import` does with polls whose id already exists in the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// import nothing
+    Fail,
+    /// keep the existing poll
+    Skip,
+    /// overwrite the existing poll
+    Replace,
+    /// import the poll under a fresh id
+    NewId,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(ConflictPolicy::Fail),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "replace" => Ok(ConflictPolicy::Replace),
+            "new-id" => Ok(ConflictPolicy::NewId),
+            _ => Err(format!(
+                "unknown conflict policy {s:?}, expected fail, skip, replace or new-id"
+            )),
+        }
+    }
+}
+
+/// writes the given polls, or all polls if `selection` is empty, and returns how many were written
+pub fn export(
+    store: &dyn PollStore,
+    selection: &[PublicPollId],
+    format: ArchiveFormat,
+    mut out: impl Write,
+) -> anyhow::Result<usize> {
+    let ids = if selection.is_empty() {
+        let mut ids = vec![];
+        store.visit_poll_summaries(None, &mut |summary| {
+            ids.push(summary.id);
+            true
+        })?;
+        ids
+    } else {
+        selection.to_vec()
+    };
+    write_record(
+        &mut out,
+        format,
+        &ArchiveRecord::Header {
+            version: ARCHIVE_VERSION,
+        },
+    )?;
+    let mut voters = HashSet::new();
+    for id in &ids {
+        let poll = store
+            .get_poll(id)?
+            .with_context(|| format!("poll {} not found", id.to_str()))?;
+        let admin_secret = store
+            .admin_secret(id)?
+            .with_context(|| format!("poll {} has no admin secret", id.to_str()))?;
+        for user_id in ballot_authors(&poll) {
+            if !voters.insert(user_id.clone()) {
+                continue;
+            }
+            // ballots of voters created before tokens existed have no token
+            if let Some(token) = store.voter_token(user_id)? {
+                write_record(
+                    &mut out,
+                    format,
+                    &ArchiveRecord::Voter {
+                        user_id: user_id.clone(),
+                        token,
+                    },
+                )?;
+            }
+        }
+        write_record(
+            &mut out,
+            format,
+            &ArchiveRecord::Poll {
+                poll: Poll::V2(Box::new(poll)),
+                admin_secret,
+            },
+        )?;
+    }
+    out.flush()?;
+    Ok(ids.len())
+}
+
+fn ballot_authors(poll: &PollV2) -> impl Iterator<Item = &PublicUserId> {
+    poll.votes
+        .iter()
+        .chain(poll.vote_history.iter().map(|v| &v.vote))
+        .map(|v| &v.user_id)
+}
+
+fn write_record(
+    out: &mut impl Write,
+    format: ArchiveFormat,
+    record: &ArchiveRecord,
+) -> anyhow::Result<()> {
+    match format {
+        ArchiveFormat::JsonLines => {
+            serde_json::to_writer(&mut *out, record)?;
+            out.write_all(b"\n")?;
+        }
+        ArchiveFormat::Cbor => serde_cbor::to_writer(&mut *out, record)?,
+    }
+    Ok(())
+}
+
+/// reads a whole archive and checks its header
+pub fn read_archive(format: ArchiveFormat, input: impl Read) -> anyhow::Result<Vec<ArchiveRecord>> {
+    let records: Vec<ArchiveRecord> = match format {
+        ArchiveFormat::JsonLines => serde_json::Deserializer::from_reader(input)
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .context("invalid JSON Lines archive")?,
+        ArchiveFormat::Cbor => serde_cbor::Deserializer::from_reader(input)
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .context("invalid CBOR archive")?,
+    };
+    match records.first() {
+        Some(ArchiveRecord::Header { version }) if *version <= ARCHIVE_VERSION => Ok(records),
+        Some(ArchiveRecord::Header { version }) => {
+            bail!("archive version {version} is newer than the supported version {ARCHIVE_VERSION}")
+        }
+        _ => bail!("archive doesn't start with a header"),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// ids that already existed and were kept
+    pub skipped: Vec<PublicPollId>,
+    pub replaced: Vec<PublicPollId>,
+    /// (id in the archive, id it was imported under)
+    pub renamed: Vec<(PublicPollId, PublicPollId)>,
+    /// voters that already exist with a different token. their existing token is kept
+    pub conflicting_voters: Vec<PublicUserId>,
+}
+
+/// writes the records of an archive into the store. voters are only imported along with a poll
+/// they voted in, so that skipping a poll skips its voters too
+pub fn import(
+    store: &dyn PollStore,
+    records: Vec<ArchiveRecord>,
+    on_conflict: ConflictPolicy,
+) -> anyhow::Result<ImportReport> {
+    if on_conflict == ConflictPolicy::Fail {
+        let mut archived = HashSet::new();
+        let mut existing = vec![];
+        for record in &records {
+            if let ArchiveRecord::Poll { poll, .. } = record {
+                let id = poll.id().to_str();
+                if !archived.insert(id) {
+                    bail!("poll {id} appears more than once in the archive");
+                }
+                if store.get_poll(poll.id())?.is_some() {
+                    existing.push(id);
+                }
+            }
+        }
+        if !existing.is_empty() {
+            bail!(
+                "polls already exist: {}. choose another --on-conflict policy",
+                existing.join(", ")
+            );
+        }
+    }
+    let mut report = ImportReport::default();
+    let mut voters = vec![];
+    let mut imported_voters = HashSet::new();
+    for record in records {
+        match record {
+            ArchiveRecord::Header { .. } => {}
+            ArchiveRecord::Voter { user_id, token } => voters.push((user_id, token)),
+            ArchiveRecord::Poll { poll, admin_secret } => {
+                let mut poll = poll.into_latest();
+                // polls of older versions lose their result when they are upgraded
+                if poll.result.is_none() {
+                    poll.result = Some(tally::compute_vote_result(&poll));
+                }
+                if store.get_poll(&poll.id)?.is_some() {
+                    match on_conflict {
+                        ConflictPolicy::Fail | ConflictPolicy::Skip => {
+                            report.skipped.push(poll.id);
+                            continue;
+                        }
+                        ConflictPolicy::Replace => report.replaced.push(poll.id.clone()),
+                        ConflictPolicy::NewId => {
+                            let new_id = PublicPollId::from_str(nanoid::nanoid!());
+                            let old_id = std::mem::replace(&mut poll.id, new_id.clone());
+                            report.renamed.push((old_id, new_id));
+                        }
+                    }
+                }
+                store.insert_poll(&poll, &admin_secret)?;
+                imported_voters.extend(ballot_authors(&poll).cloned());
+                report.imported += 1;
+            }
+        }
+    }
+    for (user_id, token) in voters {
+        if !imported_voters.contains(&user_id) {
+            continue;
+        }
+        match store.voter_token(&user_id)? {
+            Some(existing) if existing != token => report.conflicting_voters.push(user_id),
+            Some(_) => {}
+            None => store.insert_voter(&user_id, &token)?,
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{PollOption, PollOptionId, PollV1, ScoreVote};
+
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn poll(id: &str, title: &str) -> PollV2 {
+        let option = |id: &str| PollOption {
+            id: PollOptionId::from_str(id.to_string()),
+            title: id.to_string(),
+            description_text_markdown: String::new(),
+        };
+        let mut poll = PollV2::from(PollV1 {
+            id: PublicPollId::from_str(id),
+            title: title.to_string(),
+            description_text_markdown: String::new(),
+            options: vec![option("a"), option("b")],
+            votes: vec![ScoreVote {
+                user_id: PublicUserId::from_str(format!("voter of {id}")),
+                user_name: "voter".to_string(),
+                votes: [
+                    (PollOptionId::from_str("a".to_string()), Some(7.0)),
+                    (PollOptionId::from_str("b".to_string()), None),
+                ]
+                .into(),
+            }],
+            result: None,
+        });
+        poll.result = Some(tally::compute_vote_result(&poll));
+        poll
+    }
+
+    fn secret(id: &str) -> AdminSecret {
+        AdminSecret::new(format!("secret of {id}"))
+    }
+
+    fn store_with(polls: &[(&str, &str)]) -> MemoryStore {
+        let store = MemoryStore::default();
+        for (id, title) in polls {
+            let poll = poll(id, title);
+            store.insert_poll(&poll, &secret(id)).unwrap();
+            store
+                .insert_voter(
+                    &poll.votes[0].user_id,
+                    &VoterToken::new(format!("token of {id}")),
+                )
+                .unwrap();
+        }
+        store
+    }
+
+    fn archive(store: &MemoryStore, format: ArchiveFormat) -> Vec<ArchiveRecord> {
+        let mut out = vec![];
+        export(store, &[], format, &mut out).unwrap();
+        read_archive(format, out.as_slice()).unwrap()
+    }
+
+    fn title(store: &MemoryStore, id: &str) -> String {
+        store
+            .get_poll(&PublicPollId::from_str(id))
+            .unwrap()
+            .unwrap()
+            .title
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        for format in [ArchiveFormat::JsonLines, ArchiveFormat::Cbor] {
+            let source = store_with(&[("p1", "first"), ("p2", "second")]);
+            let target = MemoryStore::default();
+            let report = import(&target, archive(&source, format), ConflictPolicy::Fail).unwrap();
+            assert_eq!(report.imported, 2);
+            for id in ["p1", "p2"] {
+                let id = PublicPollId::from_str(id);
+                let original = source.get_poll(&id).unwrap().unwrap();
+                let imported = target.get_poll(&id).unwrap().unwrap();
+                assert_eq!(
+                    serde_json::to_value(&imported).unwrap(),
+                    serde_json::to_value(&original).unwrap()
+                );
+                assert_eq!(
+                    target.admin_secret(&id).unwrap(),
+                    source.admin_secret(&id).unwrap()
+                );
+                let voter = &original.votes[0].user_id;
+                assert_eq!(
+                    target.voter_token(voter).unwrap(),
+                    source.voter_token(voter).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fail_imports_nothing_if_a_poll_exists() {
+        let records = archive(
+            &store_with(&[("p1", "archived"), ("p2", "new")]),
+            ArchiveFormat::JsonLines,
+        );
+        let target = store_with(&[("p1", "existing")]);
+        assert!(import(&target, records, ConflictPolicy::Fail).is_err());
+        assert_eq!(title(&target, "p1"), "existing");
+        assert!(target
+            .get_poll(&PublicPollId::from_str("p2"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn fail_rejects_a_poll_archived_twice() {
+        let mut records = archive(&store_with(&[("p1", "first")]), ArchiveFormat::JsonLines);
+        records.extend(archive(
+            &store_with(&[("p1", "second")]),
+            ArchiveFormat::JsonLines,
+        ));
+        let target = MemoryStore::default();
+        assert!(import(&target, records, ConflictPolicy::Fail).is_err());
+        assert!(target
+            .get_poll(&PublicPollId::from_str("p1"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn skip_keeps_the_existing_poll() {
+        let source = store_with(&[("p1", "archived"), ("p2", "archived")]);
+        let records = archive(&source, ArchiveFormat::JsonLines);
+        // without the voter of p1
+        let target = MemoryStore::default();
+        target
+            .insert_poll(&poll("p1", "existing"), &secret("p1"))
+            .unwrap();
+        let report = import(&target, records, ConflictPolicy::Skip).unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.skipped, vec![PublicPollId::from_str("p1")]);
+        assert_eq!(title(&target, "p1"), "existing");
+        assert_eq!(title(&target, "p2"), "archived");
+        // the voter of the skipped poll stays out, the one of the imported poll comes along
+        let voter = |id: &str| PublicUserId::from_str(format!("voter of {id}"));
+        assert_eq!(target.voter_token(&voter("p1")).unwrap(), None);
+        assert_eq!(
+            target.voter_token(&voter("p2")).unwrap(),
+            Some(VoterToken::new("token of p2"))
+        );
+    }
+
+    #[test]
+    fn replace_overwrites_the_existing_poll() {
+        let records = archive(&store_with(&[("p1", "archived")]), ArchiveFormat::JsonLines);
+        let target = store_with(&[("p1", "existing")]);
+        let report = import(&target, records, ConflictPolicy::Replace).unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.replaced, vec![PublicPollId::from_str("p1")]);
+        assert_eq!(title(&target, "p1"), "archived");
+    }
+
+    #[test]
+    fn new_id_imports_next_to_the_existing_poll() {
+        let records = archive(&store_with(&[("p1", "archived")]), ArchiveFormat::JsonLines);
+        let target = store_with(&[("p1", "existing")]);
+        let report = import(&target, records, ConflictPolicy::NewId).unwrap();
+        assert_eq!(report.imported, 1);
+        let (old, new) = &report.renamed[0];
+        assert_eq!(old, &PublicPollId::from_str("p1"));
+        assert_eq!(title(&target, "p1"), "existing");
+        assert_eq!(title(&target, new.to_str()), "archived");
+        assert_eq!(target.admin_secret(new).unwrap(), Some(secret("p1")));
+    }
+}
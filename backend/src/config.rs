@@ -0,0 +1,173 @@
+//! server settings. read from a TOML file, then overridden by environment variables and
+//! command line flags
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::Context;
+use serde::Deserialize;
+use structopt::StructOpt;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub storage: StorageKind,
+    /// directory of the sled database or file of the SQLite database.
+    /// unused by the in-memory store
+    pub database: PathBuf,
+    /// address of the HTTP endpoint
+    pub listen: String,
+    /// address of the websocket endpoint, which also serves live updates of polls
+    pub ws_listen: String,
+    /// worker threads of the HTTP server
+    pub threads: usize,
+    /// origins that may call the api from a browser. empty keeps the server's default
+    pub cors_origins: Vec<String>,
+    /// output directory of `trunk build`, served next to the HTTP endpoint if set
+    pub static_dir: Option<PathBuf>,
+    pub limits: Limits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            storage: StorageKind::Sled,
+            database: "server-database.sled".into(),
+            listen: "127.0.0.1:3030".to_string(),
+            ws_listen: "127.0.0.1:3031".to_string(),
+            threads: 3,
+            cors_origins: vec![],
+            static_dir: None,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// which backend of `store::PollStore` keeps the polls
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    Sled,
+    Sqlite,
+    /// everything is lost when the server stops
+    Memory,
+}
+
+impl FromStr for StorageKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sled" => Ok(StorageKind::Sled),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            "memory" => Ok(StorageKind::Memory),
+            _ => Err(format!(
+                "unknown storage {s:?}, expected sled, sqlite or memory"
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// in bytes, for both HTTP requests and websocket messages
+    pub max_request_body_size: usize,
+    /// polls can't be created or edited to have more options than this
+    pub max_options: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_request_body_size: 1024 * 1024,
+            max_options: 200,
+        }
+    }
+}
+
+// flags shared by all commands
+#[derive(StructOpt, Debug)]
+pub struct ConfigArgs {
+    /// TOML file with the settings of `Config`
+    #[structopt(long, env = "SVT_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// where polls are stored: sled, sqlite or memory
+    #[structopt(long, env = "SVT_STORAGE")]
+    pub storage: Option<StorageKind>,
+    /// directory of the sled database or file of the SQLite database
+    #[structopt(long, env = "SVT_DATABASE")]
+    pub database: Option<PathBuf>,
+}
+
+impl ConfigArgs {
+    /// reads the config file, if any, and applies the flags on top of it
+    pub fn load(&self) -> anyhow::Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("could not read config file {}", path.display()))?;
+                toml::from_str(&text)
+                    .with_context(|| format!("invalid config file {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+        if let Some(storage) = self.storage {
+            config.storage = storage;
+        }
+        if let Some(database) = &self.database {
+            config.database = database.clone();
+        }
+        Ok(config)
+    }
+}
+
+// flags of the `start` command
+#[derive(StructOpt, Debug)]
+pub struct ServerArgs {
+    /// address of the HTTP endpoint (format: 127.0.0.1:3030)
+    #[structopt(env = "SVT_LISTEN")]
+    pub listen: Option<String>,
+    /// address of the websocket endpoint (format: 127.0.0.1:3031)
+    #[structopt(long, env = "SVT_WS_LISTEN")]
+    pub ws_listen: Option<String>,
+    /// worker threads of the HTTP server
+    #[structopt(long, env = "SVT_THREADS")]
+    pub threads: Option<usize>,
+    /// origin that may call the api from a browser, can be given several times
+    #[structopt(long = "cors-origin", env = "SVT_CORS_ORIGINS", use_delimiter = true)]
+    pub cors_origins: Vec<String>,
+    /// output directory of `trunk build` to serve the frontend from
+    #[structopt(long, env = "SVT_STATIC_DIR")]
+    pub static_dir: Option<PathBuf>,
+    /// in bytes, for both HTTP requests and websocket messages
+    #[structopt(long, env = "SVT_MAX_REQUEST_BODY_SIZE")]
+    pub max_request_body_size: Option<usize>,
+    /// maximum number of options per poll
+    #[structopt(long, env = "SVT_MAX_OPTIONS")]
+    pub max_options: Option<usize>,
+}
+
+impl ServerArgs {
+    pub fn apply(self, config: &mut Config) {
+        if let Some(listen) = self.listen {
+            config.listen = listen;
+        }
+        if let Some(ws_listen) = self.ws_listen {
+            config.ws_listen = ws_listen;
+        }
+        if let Some(threads) = self.threads {
+            config.threads = threads;
+        }
+        if !self.cors_origins.is_empty() {
+            config.cors_origins = self.cors_origins;
+        }
+        if let Some(static_dir) = self.static_dir {
+            config.static_dir = Some(static_dir);
+        }
+        if let Some(size) = self.max_request_body_size {
+            config.limits.max_request_body_size = size;
+        }
+        if let Some(max_options) = self.max_options {
+            config.limits.max_options = max_options;
+        }
+    }
+}
@@ -1,164 +1,705 @@
-use std::{collections::HashMap, f32::consts::E, future};
+mod archive;
+mod config;
+mod migrations;
+mod static_files;
+mod store;
+mod tally;
+mod validation;
+
+use std::{
+    collections::{HashMap, HashSet},
+    future,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::Context;
-use common::{CreatePoll, Poll, PollOptionId, PollV1, PublicPollId, Rpc, ScoreVote};
+use archive::{ArchiveFormat, ConflictPolicy};
+use common::{
+    subscriptions::PollSubscriptions, AdminSecret, ApiError, BallotError, CreatePoll, CreatedPoll,
+    EditPoll, ListPolls, PairwiseResult, PollList, PollOption, PollStateFilter, PollSummary,
+    PollV2, PollView, PublicPollId, PublicUserId, ResultVisibility, Rpc, ScoreVote,
+    SupersededReason, SupersededVote, TallyMethod, VoterIdentity, VoterToken,
+};
+use config::{ConfigArgs, Limits, ServerArgs};
 use jsonrpc_core::BoxFuture;
-use jsonrpc_http_server::ServerBuilder;
-use serde::Serialize;
-use sled::transaction::{
-    ConflictableTransactionResult, TransactionError, TransactionResult, TransactionalTree,
+use jsonrpc_http_server::{DomainsValidation, ServerBuilder};
+use jsonrpc_pubsub::{
+    typed::{Sink, Subscriber},
+    PubSubHandler, Session, SubscriptionId,
 };
+use static_files::StaticFiles;
+use store::PollStore;
 use structopt::StructOpt;
+#[derive(Clone)]
 struct Server {
-    database: sled::Db,
+    store: Arc<dyn PollStore>,
+    limits: Limits,
+    subscriptions: Arc<Mutex<HashMap<SubscriptionId, PollSubscription>>>,
+}
+
+/// a websocket client that wants to be notified when a poll changes
+struct PollSubscription {
+    poll_id: PublicPollId,
+    /// needed to decide whether the subscriber may see the results
+    voter: Option<VoterIdentity>,
+    sink: Sink<PollView>,
+}
+
+impl Server {
+    fn load_poll(&self, id: &PublicPollId) -> Result<PollV2, ApiError> {
+        self.store.get_poll(id)?.ok_or(ApiError::PollNotFound)
+    }
+
+    /// makes sure the secret is the one that was returned when the poll was created
+    fn check_admin_secret(
+        &self,
+        poll_id: &PublicPollId,
+        secret: &AdminSecret,
+    ) -> Result<(), ApiError> {
+        let stored = self
+            .store
+            .admin_secret(poll_id)?
+            .ok_or(ApiError::PollNotFound)?;
+        if &stored != secret {
+            return Err(ApiError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    /// whether the voter has a ballot in the poll. an invalid token counts as not voted
+    fn has_voted(&self, poll: &PollV2, voter: Option<&VoterIdentity>) -> Result<bool, ApiError> {
+        let voter = match voter {
+            Some(voter) => voter,
+            None => return Ok(false),
+        };
+        if !poll.votes.iter().any(|v| v.user_id == voter.user_id) {
+            return Ok(false);
+        }
+        match self.check_voter_token(&voter.user_id, &voter.token) {
+            Ok(()) => Ok(true),
+            Err(ApiError::InvalidBallot(BallotError::InvalidVoterToken)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// makes sure the token was issued for the given user by `create_voter`
+    fn check_voter_token(
+        &self,
+        user_id: &PublicUserId,
+        token: &VoterToken,
+    ) -> Result<(), ApiError> {
+        let valid = self.store.voter_token(user_id)?.as_ref() == Some(token);
+        if !valid {
+            return Err(BallotError::InvalidVoterToken.into());
+        }
+        Ok(())
+    }
+
+    /// closes all polls whose `closes_at` has passed. a poll that can't be closed is logged and
+    /// doesn't keep the others open
+    fn close_expired_polls(&self) -> Result<(), ApiError> {
+        let now = now();
+        // collect first, the store must not be modified while visiting it
+        let mut expired = vec![];
+        self.store.visit_poll_summaries(None, &mut |poll| {
+            if !poll.closed && poll.closes_at.is_some_and(|t| t <= now) {
+                expired.push(poll.id);
+            }
+            true
+        })?;
+        for poll_id in expired {
+            let closed = self.modify_poll(&poll_id, |poll| {
+                poll.closed = true;
+                Ok(())
+            });
+            if let Err(e) = closed {
+                eprintln!("could not close expired poll {}: {e}", poll_id.to_str());
+            }
+        }
+        Ok(())
+    }
+
+    /// applies `f` through [`PollStore::update_poll`], recomputes the result and notifies the
+    /// subscribers
+    fn modify_poll(
+        &self,
+        poll_id: &PublicPollId,
+        f: impl Fn(&mut PollV2) -> Result<(), ApiError>,
+    ) -> Result<PollV2, ApiError> {
+        let poll = self.store.update_poll(poll_id, &|poll| {
+            f(poll)?;
+            // closed polls don't accept ballots, but reopening or adding options still changes
+            // the result
+            poll.result = Some(tally::compute_vote_result(poll));
+            Ok(())
+        })?;
+        self.notify_subscribers(&poll);
+        Ok(poll)
+    }
+
+    fn too_many_options(&self) -> ApiError {
+        invalid_poll(format!(
+            "a poll can have at most {} options",
+            self.limits.max_options
+        ))
+    }
+
+    /// sends the new state of the poll to everyone subscribed to it
+    fn notify_subscribers(&self, poll: &PollV2) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|_, s| {
+            if s.poll_id != poll.id {
+                return true;
+            }
+            let has_voted = self.has_voted(poll, s.voter.as_ref()).unwrap_or(false);
+            match s.sink.notify(Ok(public_poll(poll.clone(), has_voted))) {
+                // the client is gone
+                Err(e) if e.is_disconnected() => false,
+                _ => true,
+            }
+        });
+    }
+
+    /// tells everyone subscribed to a deleted poll that it is gone and drops their subscriptions
+    fn end_subscriptions(&self, poll_id: &PublicPollId) {
+        self.subscriptions.lock().unwrap().retain(|_, s| {
+            if s.poll_id != *poll_id {
+                return true;
+            }
+            let _ = s.sink.notify(Err(ApiError::PollNotFound.into()));
+            false
+        });
+    }
+}
+
+fn invalid_poll(reason: impl Into<String>) -> ApiError {
+    ApiError::InvalidPoll {
+        reason: reason.into(),
+    }
+}
+
+/// removes the parts of a poll that only the admin may see
+/// `has_voted` tells whether the requester has a ballot in the poll
+fn public_poll(mut poll: PollV2, has_voted: bool) -> PollView {
+    poll.vote_history.clear();
+    if !results_visible(&poll, has_voted) {
+        poll.votes.clear();
+        poll.result = None;
+        return PollView {
+            poll,
+            results_hidden: true,
+            aggregates: None,
+        };
+    }
+    anonymize(poll)
+}
+
+/// for anonymous polls, replaces the ballots with aggregates. even the admin doesn't see them
+fn anonymize(mut poll: PollV2) -> PollView {
+    if !poll.anonymous {
+        return PollView {
+            poll,
+            results_hidden: false,
+            aggregates: None,
+        };
+    }
+    let aggregates = Some(tally::ballot_aggregates(&poll));
+    poll.votes.clear();
+    poll.vote_history.clear();
+    if let Some(committee) = poll.result.as_mut().and_then(|r| r.committee.as_mut()) {
+        for round in &mut committee.rounds {
+            round.ballot_weights.clear();
+        }
+    }
+    PollView {
+        poll,
+        results_hidden: false,
+        aggregates,
+    }
+}
+
+/// what the poll admin gets to see
+fn admin_poll(poll: PollV2) -> PollView {
+    anonymize(poll)
 }
 
-#[derive(Debug, Serialize)]
-pub struct OurError {
-    // todo: better variants
-    msg: String,
+/// whether the ballots and the result of the poll may be shown to the requester
+fn results_visible(poll: &PollV2, has_voted: bool) -> bool {
+    match poll.result_visibility {
+        ResultVisibility::Always => true,
+        ResultVisibility::AfterVoting => has_voted || poll.closed,
+        ResultVisibility::AfterClosing => poll.closed,
+    }
+}
+
+/// moves the ballot of the user from the tally to the vote history
+fn supersede_ballot(
+    poll: &mut PollV2,
+    user_id: &PublicUserId,
+    reason: SupersededReason,
+) -> Result<(), BallotError> {
+    let index = poll
+        .votes
+        .iter()
+        .position(|v| &v.user_id == user_id)
+        .ok_or(BallotError::NoExistingBallot)?;
+    let vote = poll.votes.remove(index);
+    poll.vote_history.push(SupersededVote {
+        vote,
+        reason,
+        superseded_at: now(),
+    });
+    Ok(())
 }
 
-impl From<OurError> for jsonrpc_core::Error {
-    fn from(e: OurError) -> Self {
-        jsonrpc_core::Error {
-            code: jsonrpc_core::ErrorCode::ServerError(1),
-            message: e.msg,
-            data: None,
+/// adds the ballot to the poll, moving an earlier ballot of the same user to the history
+fn store_ballot(poll: &mut PollV2, vote: ScoreVote) {
+    match poll.votes.iter_mut().find(|v| v.user_id == vote.user_id) {
+        Some(existing) => {
+            let old = std::mem::replace(existing, vote);
+            poll.vote_history.push(SupersededVote {
+                vote: old,
+                reason: SupersededReason::Replaced,
+                superseded_at: now(),
+            });
         }
+        None => poll.votes.push(vote),
     }
 }
-impl From<anyhow::Error> for OurError {
-    fn from(e: anyhow::Error) -> Self {
-        OurError { msg: e.to_string() }
+
+/// current unix timestamp in seconds
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// fails if two options share an id, they would be indistinguishable on ballots
+fn check_unique_option_ids<'a>(
+    options: impl IntoIterator<Item = &'a PollOption>,
+) -> Result<(), ApiError> {
+    let mut seen = HashSet::new();
+    for option in options {
+        if !seen.insert(&option.id) {
+            return Err(invalid_poll(format!(
+                "option id {:?} is already used",
+                option.id.to_str()
+            )));
+        }
     }
+    Ok(())
 }
 
 // Server implementation
-impl Rpc<OurError> for Server {
-    fn add(&self, a: u64, b: u64) -> Result<u64, OurError> {
+impl Rpc<ApiError> for Server {
+    fn add(&self, a: u64, b: u64) -> Result<u64, ApiError> {
         Ok(a + b)
     }
 
-    fn create_poll(&self, poll: CreatePoll) -> Result<Poll, OurError> {
+    fn create_poll(&self, poll: CreatePoll) -> Result<CreatedPoll, ApiError> {
+        if let TallyMethod::TrimmedMean { trim } = poll.tally_method {
+            if !(0.0..0.5).contains(&trim) {
+                return Err(invalid_poll("trim fraction must be in [0, 0.5)"));
+            }
+        }
+        poll.score_scale
+            .validate()
+            .map_err(|e| invalid_poll(format!("invalid score scale: {e}")))?;
+        if poll.options.len() > self.limits.max_options {
+            return Err(self.too_many_options());
+        }
+        check_unique_option_ids(&poll.options)?;
+        if poll.seats < 1 || poll.seats as usize > poll.options.len() {
+            return Err(invalid_poll(
+                "number of seats must be between 1 and the number of options",
+            ));
+        }
+        if let (Some(opens_at), Some(closes_at)) = (poll.opens_at, poll.closes_at) {
+            if opens_at >= closes_at {
+                return Err(invalid_poll("poll must open before it closes"));
+            }
+        }
+        if poll.closes_at.is_some_and(|t| t <= now()) {
+            return Err(invalid_poll("poll can't close in the past"));
+        }
         let id = PublicPollId::from_str(nanoid::nanoid!());
-        let poll = Poll::V1(PollV1 {
-            id: id.clone(),
+        let poll = PollV2 {
+            id,
             title: poll.title,
             description_text_markdown: poll.description_text_markdown,
             options: poll.options,
+            score_scale: poll.score_scale,
             votes: vec![],
+            tally_method: poll.tally_method,
+            seats: poll.seats,
+            proportional_method: poll.proportional_method,
             result: None,
-        });
-        let polls = self
-            .database
-            .open_tree("polls")
-            .context("opening database")?;
-        polls
-            .insert(
-                &serde_cbor::to_vec(&id).context("serializing")?,
-                serde_cbor::to_vec(&poll).context("serializing")?,
-            )
-            .context("inserting into db")?;
-        Ok(poll)
+            vote_history: vec![],
+            closed: false,
+            opens_at: poll.opens_at,
+            closes_at: poll.closes_at,
+            result_visibility: poll.result_visibility,
+            anonymous: poll.anonymous,
+        };
+        let admin_secret = AdminSecret::new(nanoid::nanoid!(32));
+        self.store.insert_poll(&poll, &admin_secret)?;
+        let poll = admin_poll(poll);
+        Ok(CreatedPoll { poll, admin_secret })
+    }
+
+    fn get_poll(
+        &self,
+        id: PublicPollId,
+        voter: Option<VoterIdentity>,
+    ) -> Result<PollView, ApiError> {
+        let poll = self.load_poll(&id)?;
+        let has_voted = self.has_voted(&poll, voter.as_ref())?;
+        Ok(public_poll(poll, has_voted))
     }
 
-    fn get_poll(&self, id: PublicPollId) -> Result<Poll, OurError> {
-        let polls = self
-            .database
-            .open_tree("polls")
-            .context("opening database")?;
-        let id = &serde_cbor::to_vec(&id).context("serializing")?;
-        let poll_ser = polls
-            .get(&id)
-            .context("loading")?
-            .context("poll not found")?;
-        Ok(serde_cbor::from_slice::<Poll>(&poll_ser).context("deserializing")?)
+    fn list_polls(&self, query: ListPolls) -> Result<PollList, ApiError> {
+        let limit = query.limit.clamp(1, ListPolls::MAX_LIMIT) as usize;
+        let needle = query.title_contains.map(|t| t.to_lowercase());
+        let mut polls = vec![];
+        let mut next_cursor = None;
+        self.store
+            .visit_poll_summaries(query.cursor.as_ref(), &mut |summary| {
+                let state_matches = match query.state {
+                    PollStateFilter::Any => true,
+                    PollStateFilter::Open => !summary.closed,
+                    PollStateFilter::Closed => summary.closed,
+                };
+                let title_matches = needle
+                    .as_ref()
+                    .is_none_or(|n| summary.title.to_lowercase().contains(n));
+                if !state_matches || !title_matches {
+                    return true;
+                }
+                if polls.len() == limit {
+                    next_cursor = polls.last().map(|p: &PollSummary| p.id.clone());
+                    return false;
+                }
+                polls.push(summary);
+                true
+            })?;
+        Ok(PollList { polls, next_cursor })
     }
 
-    fn call(&self, _: u64) -> BoxFuture<Result<String, OurError>> {
+    fn get_pairwise(
+        &self,
+        poll_id: PublicPollId,
+        voter: Option<VoterIdentity>,
+    ) -> Result<PairwiseResult, ApiError> {
+        let poll = self.load_poll(&poll_id)?;
+        if !results_visible(&poll, self.has_voted(&poll, voter.as_ref())?) {
+            return Err(ApiError::ResultsHidden);
+        }
+        Ok(tally::pairwise(&poll.options, &poll.votes))
+    }
+
+    fn call(&self, _: u64) -> BoxFuture<Result<String, ApiError>> {
         Box::pin(future::ready(Ok("OK".to_owned())))
     }
 
-    fn vote(&self, poll_id: PublicPollId, vote: common::ScoreVote) -> Result<Poll, OurError> {
-        let polls = self
-            .database
-            .open_tree("polls")
-            .context("opening database")?;
-        let poll = polls
-            .transaction(
-                |polls: &TransactionalTree| -> ConflictableTransactionResult<Poll, anyhow::Error> {
-                    use sled::transaction::ConflictableTransactionError::Abort;
-                    let id_ser = serde_cbor::to_vec(&poll_id)
-                        .context("serializing")
-                        .map_err(Abort)?;
-                    let mut poll = {
-                        let poll_ser = polls
-                            .get(&id_ser)
-                            .context("loading")
-                            .map_err(Abort)?
-                            .context("poll not found")
-                            .map_err(Abort)?;
-                        let x = serde_cbor::from_slice::<Poll>(&poll_ser)
-                            .context("deserializing")
-                            .map_err(Abort)?;
-                        let Poll::V1(p) = x;
-                        p
-                    };
-                    poll.votes.push(vote.clone());
-                    poll.result = Some(compute_vote_result(&poll.votes));
-                    let poll = Poll::V1(poll);
-                    let ser = serde_cbor::to_vec(&poll)
-                        .context("serializing")
-                        .map_err(Abort)?;
-                    polls.insert(id_ser, ser)?;
-                    Ok(poll)
-                },
-            )
-            .map_err(|e| match e {
-                TransactionError::Abort(e) => e,
-                TransactionError::Storage(e) => anyhow::anyhow!("sled error: {e}"),
-            })
-            .context("error in transaction")?;
-        Ok(poll)
+    fn create_voter(&self) -> Result<VoterIdentity, ApiError> {
+        let identity = VoterIdentity {
+            user_id: PublicUserId::from_str(nanoid::nanoid!()),
+            token: VoterToken::new(nanoid::nanoid!(32)),
+        };
+        self.store
+            .insert_voter(&identity.user_id, &identity.token)?;
+        Ok(identity)
+    }
+
+    fn vote(
+        &self,
+        poll_id: PublicPollId,
+        vote: ScoreVote,
+        token: VoterToken,
+    ) -> Result<PollView, ApiError> {
+        self.check_voter_token(&vote.user_id, &token)?;
+        self.modify_poll(&poll_id, |poll| {
+            validation::check_open(poll, now())?;
+            validation::validate_ballot(poll, &vote)?;
+            store_ballot(poll, vote.clone());
+            Ok(())
+        })
+        .map(|p| public_poll(p, true))
+    }
+
+    fn update_vote(
+        &self,
+        poll_id: PublicPollId,
+        vote: ScoreVote,
+        token: VoterToken,
+    ) -> Result<PollView, ApiError> {
+        self.check_voter_token(&vote.user_id, &token)?;
+        self.modify_poll(&poll_id, |poll| {
+            validation::check_open(poll, now())?;
+            if !poll.votes.iter().any(|v| v.user_id == vote.user_id) {
+                return Err(BallotError::NoExistingBallot.into());
+            }
+            validation::validate_ballot(poll, &vote)?;
+            store_ballot(poll, vote.clone());
+            Ok(())
+        })
+        .map(|p| public_poll(p, true))
+    }
+
+    fn retract_vote(
+        &self,
+        poll_id: PublicPollId,
+        user_id: PublicUserId,
+        token: VoterToken,
+    ) -> Result<PollView, ApiError> {
+        self.check_voter_token(&user_id, &token)?;
+        self.modify_poll(&poll_id, |poll| {
+            validation::check_open(poll, now())?;
+            supersede_ballot(poll, &user_id, SupersededReason::Retracted)?;
+            Ok(())
+        })
+        .map(|p| public_poll(p, false))
+    }
+
+    fn get_poll_admin(
+        &self,
+        poll_id: PublicPollId,
+        secret: AdminSecret,
+    ) -> Result<PollView, ApiError> {
+        self.check_admin_secret(&poll_id, &secret)?;
+        Ok(admin_poll(self.load_poll(&poll_id)?))
+    }
+
+    fn close_poll(&self, poll_id: PublicPollId, secret: AdminSecret) -> Result<PollView, ApiError> {
+        self.check_admin_secret(&poll_id, &secret)?;
+        self.modify_poll(&poll_id, |poll| {
+            poll.closed = true;
+            Ok(())
+        })
+        .map(admin_poll)
     }
-}
 
-fn compute_vote_result(
-    votes: &[ScoreVote],
-) -> std::collections::HashMap<PollOptionId, Option<f64>> {
-    let mut map: HashMap<PollOptionId, Vec<f64>> = HashMap::new();
-    for vote in votes {
-        for (id, r) in &vote.votes {
-            if let Some(r) = r {
-                map.entry(id.clone()).or_insert_with(|| vec![]).push(*r);
+    fn reopen_poll(
+        &self,
+        poll_id: PublicPollId,
+        secret: AdminSecret,
+    ) -> Result<PollView, ApiError> {
+        self.check_admin_secret(&poll_id, &secret)?;
+        self.modify_poll(&poll_id, |poll| {
+            poll.closed = false;
+            // otherwise the poll would be closed again right away
+            if poll.closes_at.is_some_and(|t| t <= now()) {
+                poll.closes_at = None;
             }
+            Ok(())
+        })
+        .map(admin_poll)
+    }
+
+    fn edit_poll(
+        &self,
+        poll_id: PublicPollId,
+        secret: AdminSecret,
+        edit: EditPoll,
+    ) -> Result<PollView, ApiError> {
+        self.check_admin_secret(&poll_id, &secret)?;
+        self.modify_poll(&poll_id, |poll| {
+            check_unique_option_ids(poll.options.iter().chain(&edit.add_options))?;
+            if let Some(title) = &edit.title {
+                poll.title = title.clone();
+            }
+            if let Some(description) = &edit.description_text_markdown {
+                poll.description_text_markdown = description.clone();
+            }
+            if poll.options.len() + edit.add_options.len() > self.limits.max_options {
+                return Err(self.too_many_options());
+            }
+            poll.options.extend(edit.add_options.iter().cloned());
+            Ok(())
+        })
+        .map(admin_poll)
+    }
+
+    fn delete_poll(&self, poll_id: PublicPollId, secret: AdminSecret) -> Result<(), ApiError> {
+        self.check_admin_secret(&poll_id, &secret)?;
+        self.store.delete_poll(&poll_id)?;
+        self.end_subscriptions(&poll_id);
+        Ok(())
+    }
+
+    fn remove_vote(
+        &self,
+        poll_id: PublicPollId,
+        secret: AdminSecret,
+        user_id: PublicUserId,
+    ) -> Result<PollView, ApiError> {
+        self.check_admin_secret(&poll_id, &secret)?;
+        self.modify_poll(&poll_id, |poll| {
+            supersede_ballot(poll, &user_id, SupersededReason::RemovedByAdmin)?;
+            Ok(())
+        })
+        .map(admin_poll)
+    }
+}
+
+impl PollSubscriptions for Server {
+    type Metadata = Arc<Session>;
+
+    fn subscribe_poll(
+        &self,
+        meta: Self::Metadata,
+        subscriber: Subscriber<PollView>,
+        poll_id: PublicPollId,
+        voter: Option<VoterIdentity>,
+    ) {
+        if let Err(e) = self.load_poll(&poll_id) {
+            let _ = subscriber.reject(e.into());
+            return;
         }
+        let id = SubscriptionId::String(nanoid::nanoid!());
+        if let Ok(sink) = subscriber.assign_id(id.clone()) {
+            let subscription = PollSubscription {
+                poll_id,
+                voter,
+                sink,
+            };
+            self.subscriptions
+                .lock()
+                .unwrap()
+                .insert(id.clone(), subscription);
+            // clients that disconnect don't unsubscribe
+            let subscriptions = Arc::clone(&self.subscriptions);
+            meta.on_drop(move || {
+                subscriptions.lock().unwrap().remove(&id);
+            });
+        }
+    }
+
+    fn unsubscribe_poll(
+        &self,
+        _meta: Option<Self::Metadata>,
+        id: SubscriptionId,
+    ) -> jsonrpc_core::Result<bool> {
+        Ok(self.subscriptions.lock().unwrap().remove(&id).is_some())
     }
-    map.into_iter()
-        .map(|(k, v)| (k, Some(v.iter().sum::<f64>() / (v.len() as f64))))
-        .collect()
+}
+
+/// how often the background task looks for polls that have passed their `closes_at`
+const POLL_CLOSER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn spawn_poll_closer(server: Server) {
+    std::thread::spawn(move || loop {
+        if let Err(e) = server.close_expired_polls() {
+            eprintln!("could not close expired polls: {e}");
+        }
+        std::thread::sleep(POLL_CLOSER_INTERVAL);
+    });
 }
 
 #[derive(StructOpt)]
 #[structopt()]
 enum Commands {
-    Start { listen: String },
-    Dump {},
+    /// runs the HTTP and websocket servers
+    Start {
+        #[structopt(flatten)]
+        config: ConfigArgs,
+        #[structopt(flatten)]
+        server: ServerArgs,
+    },
+    /// writes polls with their ballots, admin secrets and voter tokens to an archive
+    Export {
+        #[structopt(flatten)]
+        config: ConfigArgs,
+        /// file to write the archive to. stdout if not given
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+        /// jsonl or cbor
+        #[structopt(long, default_value = "jsonl")]
+        format: ArchiveFormat,
+        /// id of a poll to export, can be given several times. all polls if not given
+        #[structopt(long = "poll")]
+        polls: Vec<String>,
+    },
+    /// adds the polls of an archive written by `export`
+    Import {
+        #[structopt(flatten)]
+        config: ConfigArgs,
+        /// archive to read
+        input: PathBuf,
+        /// jsonl or cbor
+        #[structopt(long, default_value = "jsonl")]
+        format: ArchiveFormat,
+        /// what to do with polls that already exist: fail, skip, replace or new-id
+        #[structopt(long, default_value = "fail")]
+        on_conflict: ConflictPolicy,
+    },
+    /// upgrades the database to the schema version of this build
+    Migrate {
+        #[structopt(flatten)]
+        config: ConfigArgs,
+        /// only print what would be migrated
+        #[structopt(long)]
+        dry_run: bool,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
     match Commands::from_args() {
-        Commands::Start { listen } => {
+        Commands::Start { config, server } => {
+            let mut config = config.load()?;
+            server.apply(&mut config);
+            let store = store::open(&config)?;
+            migrations::check_schema_version(&*store)?;
             let mut io = jsonrpc_core::IoHandler::new();
             let rpc_server = Server {
-                database: sled::open("server-database.sled")?,
+                store,
+                limits: config.limits,
+                subscriptions: Default::default(),
             };
-            io.extend_with(rpc_server.to_delegate());
-            let jsonrpc_server = ServerBuilder::new(io)
-                .threads(3)
+            spawn_poll_closer(rpc_server.clone());
+            io.extend_with(Rpc::to_delegate(rpc_server.clone()));
+
+            let mut ws_io = PubSubHandler::new(jsonrpc_core::MetaIoHandler::default());
+            ws_io.extend_with(Rpc::to_delegate(rpc_server.clone()));
+            ws_io.extend_with(PollSubscriptions::to_delegate(rpc_server));
+            let mut ws_builder = jsonrpc_ws_server::ServerBuilder::with_meta_extractor(
+                ws_io,
+                |context: &jsonrpc_ws_server::RequestContext| {
+                    Arc::new(Session::new(context.sender()))
+                },
+            )
+            .max_payload(config.limits.max_request_body_size);
+            let mut http_builder = ServerBuilder::new(io)
+                .threads(config.threads)
+                .max_request_body_size(config.limits.max_request_body_size);
+            if let Some(static_dir) = &config.static_dir {
+                http_builder =
+                    http_builder.request_middleware(StaticFiles::new(static_dir.clone())?);
+            }
+            if !config.cors_origins.is_empty() {
+                http_builder = http_builder.cors(DomainsValidation::AllowOnly(
+                    config.cors_origins.iter().map(|o| o.into()).collect(),
+                ));
+                if !config.cors_origins.iter().any(|o| o == "*") {
+                    ws_builder = ws_builder.allowed_origins(DomainsValidation::AllowOnly(
+                        config.cors_origins.iter().map(|o| o.into()).collect(),
+                    ));
+                }
+            }
+
+            let _ws_server =
+                ws_builder
+                    .start(&config.ws_listen.parse().context(
+                        "could not parse websocket listen address (format: 127.0.0.1:3031)",
+                    )?)
+                    .context("could not start websocket server")?;
+            let jsonrpc_server = http_builder
                 .start_http(
-                    &listen
+                    &config
+                        .listen
                         .parse()
                         .context("could not parse listen address (format: 127.0.0.1:3030)")?,
                 )
@@ -167,15 +708,536 @@ fn main() -> anyhow::Result<()> {
             jsonrpc_server.wait();
             Ok(())
         }
-        Commands::Dump {} => {
-            let db = sled::open("server-database.sled")?;
-            let tree = db.open_tree("polls")?;
-            for ele in tree.iter() {
-                let (_k, v) = ele?;
-                let poll: Poll = serde_cbor::from_slice(&v)?;
-                println!("{}", serde_json::to_string_pretty(&poll)?);
+        Commands::Export {
+            config,
+            output,
+            format,
+            polls,
+        } => {
+            let store = store::open(&config.load()?)?;
+            migrations::check_schema_version(&*store)?;
+            let polls: Vec<_> = polls.into_iter().map(PublicPollId::from_str).collect();
+            let count = match output {
+                Some(path) => {
+                    let file = std::fs::File::create(&path)
+                        .with_context(|| format!("could not create {}", path.display()))?;
+                    archive::export(&*store, &polls, format, BufWriter::new(file))?
+                }
+                None => archive::export(&*store, &polls, format, std::io::stdout().lock())?,
+            };
+            eprintln!("exported {count} polls");
+            Ok(())
+        }
+        Commands::Import {
+            config,
+            input,
+            format,
+            on_conflict,
+        } => {
+            let store = store::open(&config.load()?)?;
+            migrations::check_schema_version(&*store)?;
+            let file = std::fs::File::open(&input)
+                .with_context(|| format!("could not open {}", input.display()))?;
+            let records = archive::read_archive(format, BufReader::new(file))?;
+            let report = archive::import(&*store, records, on_conflict)?;
+            eprintln!("imported {} polls", report.imported);
+            for id in &report.skipped {
+                eprintln!("skipped {}: already exists", id.to_str());
+            }
+            for id in &report.replaced {
+                eprintln!("replaced {}", id.to_str());
+            }
+            for (old, new) in &report.renamed {
+                eprintln!("imported {} as {}", old.to_str(), new.to_str());
+            }
+            for user_id in &report.conflicting_voters {
+                eprintln!(
+                    "kept the existing token of voter {}, it differs from the archived one",
+                    user_id.to_str()
+                );
+            }
+            Ok(())
+        }
+        Commands::Migrate { config, dry_run } => {
+            let store = store::open(&config.load()?)?;
+            let report = migrations::migrate(&*store, dry_run)?;
+            if report.applied.is_empty() {
+                eprintln!(
+                    "database is already at schema version {}",
+                    migrations::SCHEMA_VERSION
+                );
+                return Ok(());
             }
+            let verb = if dry_run { "would migrate" } else { "migrated" };
+            eprintln!(
+                "{verb} {} polls from schema version {} to {}:",
+                report.polls,
+                report.from,
+                migrations::SCHEMA_VERSION
+            );
+            for description in &report.applied {
+                eprintln!("- {description}");
+            }
+            for (id, secret) in &report.new_admin_secrets {
+                if dry_run {
+                    eprintln!("poll {} would get a new admin secret", id.to_str());
+                } else {
+                    eprintln!("admin secret of poll {}: {}", id.to_str(), secret.to_str());
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::PollOptionId;
+
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn server() -> Server {
+        Server {
+            store: Arc::new(MemoryStore::default()),
+            limits: Limits::default(),
+            subscriptions: Default::default(),
+        }
+    }
+
+    /// a poll with the options "a" and "b" on the default scale
+    fn new_poll(title: &str) -> CreatePoll {
+        CreatePoll {
+            title: title.to_string(),
+            description_text_markdown: String::new(),
+            options: ["a", "b"]
+                .into_iter()
+                .map(|o| PollOption {
+                    id: PollOptionId::from_str(o.to_string()),
+                    title: o.to_string(),
+                    description_text_markdown: String::new(),
+                })
+                .collect(),
+            score_scale: Default::default(),
+            tally_method: TallyMethod::default(),
+            seats: 1,
+            proportional_method: Default::default(),
+            opens_at: None,
+            closes_at: None,
+            result_visibility: ResultVisibility::default(),
+            anonymous: false,
+        }
+    }
+
+    fn ballot(voter: &VoterIdentity, name: &str) -> ScoreVote {
+        ScoreVote {
+            user_id: voter.user_id.clone(),
+            user_name: name.to_string(),
+            votes: [
+                (PollOptionId::from_str("a".to_string()), Some(7.0)),
+                (PollOptionId::from_str("b".to_string()), Some(2.0)),
+            ]
+            .into(),
+        }
+    }
+
+    #[test]
+    fn voter_tokens_are_checked() {
+        let server = server();
+        let voter = server.create_voter().unwrap();
+        assert_eq!(
+            server.check_voter_token(&voter.user_id, &voter.token),
             Ok(())
+        );
+        let invalid = Err(BallotError::InvalidVoterToken.into());
+        let wrong = VoterToken::new("wrong");
+        assert_eq!(server.check_voter_token(&voter.user_id, &wrong), invalid);
+        let unknown = PublicUserId::from_str("unknown");
+        assert_eq!(server.check_voter_token(&unknown, &voter.token), invalid);
+    }
+
+    #[test]
+    fn has_voted_needs_a_ballot_and_the_right_token() {
+        let server = server();
+        let created = server.create_poll(new_poll("poll")).unwrap();
+        let id = created.poll.poll.id;
+        let voter = server.create_voter().unwrap();
+        let other = server.create_voter().unwrap();
+        server
+            .vote(id.clone(), ballot(&voter, "voter"), voter.token.clone())
+            .unwrap();
+        let poll = server.load_poll(&id).unwrap();
+        assert_eq!(server.has_voted(&poll, Some(&voter)), Ok(true));
+        assert_eq!(server.has_voted(&poll, None), Ok(false));
+        assert_eq!(server.has_voted(&poll, Some(&other)), Ok(false));
+        let forged = VoterIdentity {
+            user_id: voter.user_id.clone(),
+            token: other.token.clone(),
+        };
+        assert_eq!(server.has_voted(&poll, Some(&forged)), Ok(false));
+        let unknown = VoterIdentity {
+            user_id: PublicUserId::from_str("unknown"),
+            token: voter.token.clone(),
+        };
+        assert_eq!(server.has_voted(&poll, Some(&unknown)), Ok(false));
+    }
+
+    #[test]
+    fn admins_can_remove_ballots_from_closed_polls() {
+        let server = server();
+        let created = server.create_poll(new_poll("poll")).unwrap();
+        let (id, secret) = (created.poll.poll.id, created.admin_secret);
+        let voter = server.create_voter().unwrap();
+        server
+            .vote(id.clone(), ballot(&voter, "voter"), voter.token.clone())
+            .unwrap();
+        server.close_poll(id.clone(), secret.clone()).unwrap();
+        let view = server
+            .remove_vote(id.clone(), secret, voter.user_id.clone())
+            .unwrap();
+        assert!(view.poll.closed);
+        assert!(view.poll.votes.is_empty());
+        let history = server.load_poll(&id).unwrap().vote_history;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].reason, SupersededReason::RemovedByAdmin);
+        let wrong = AdminSecret::new("wrong");
+        assert_eq!(
+            server.remove_vote(id, wrong, voter.user_id).unwrap_err(),
+            ApiError::Unauthorized
+        );
+    }
+
+    #[test]
+    fn polls_cant_be_created_closed() {
+        let server = server();
+        for closes_at in [0, now() - 60, now()] {
+            let poll = CreatePoll {
+                closes_at: Some(closes_at),
+                ..new_poll("poll")
+            };
+            assert!(matches!(
+                server.create_poll(poll),
+                Err(ApiError::InvalidPoll { .. })
+            ));
+        }
+        let poll = CreatePoll {
+            closes_at: Some(now() + 60),
+            ..new_poll("poll")
+        };
+        assert!(server.create_poll(poll).is_ok());
+    }
+
+    #[test]
+    fn expired_polls_are_closed() {
+        let server = server();
+        let soon = CreatePoll {
+            closes_at: Some(now() + 60),
+            ..new_poll("soon")
+        };
+        let soon = server.create_poll(soon).unwrap().poll.poll.id;
+        let later = CreatePoll {
+            closes_at: Some(now() + 3600),
+            ..new_poll("later")
+        };
+        let later = server.create_poll(later).unwrap().poll.poll.id;
+        let open = server.create_poll(new_poll("open")).unwrap().poll.poll.id;
+        // pretend a minute has passed
+        server
+            .store
+            .update_poll(&soon, &|poll| {
+                poll.closes_at = Some(now() - 1);
+                Ok(())
+            })
+            .unwrap();
+        server.close_expired_polls().unwrap();
+        assert!(server.load_poll(&soon).unwrap().closed);
+        assert!(!server.load_poll(&later).unwrap().closed);
+        assert!(!server.load_poll(&open).unwrap().closed);
+    }
+
+    /// whether the view shows the ballots and the result, checking that it is all or nothing
+    fn shows_results(view: &PollView) -> bool {
+        let shown = !view.results_hidden;
+        assert_eq!(view.poll.result.is_some(), shown);
+        assert_eq!(view.poll.votes.is_empty() && !view.poll.anonymous, !shown);
+        assert!(view.poll.vote_history.is_empty());
+        shown
+    }
+
+    #[test]
+    fn results_are_shown_according_to_the_visibility() {
+        // visible to a stranger, to the voter, and to the stranger once the poll is closed
+        let expected = [
+            (ResultVisibility::Always, [true, true, true]),
+            (ResultVisibility::AfterVoting, [false, true, true]),
+            (ResultVisibility::AfterClosing, [false, false, true]),
+        ];
+        for (visibility, [stranger, voted, closed]) in expected {
+            let server = server();
+            let poll = CreatePoll {
+                result_visibility: visibility,
+                ..new_poll("poll")
+            };
+            let created = server.create_poll(poll).unwrap();
+            let (id, secret) = (created.poll.poll.id, created.admin_secret);
+            let voter = server.create_voter().unwrap();
+            server
+                .vote(id.clone(), ballot(&voter, "voter"), voter.token.clone())
+                .unwrap();
+            let view = server.get_poll(id.clone(), None).unwrap();
+            assert_eq!(shows_results(&view), stranger, "{visibility:?}");
+            let view = server.get_poll(id.clone(), Some(voter.clone())).unwrap();
+            assert_eq!(shows_results(&view), voted, "{visibility:?}");
+            // someone who hasn't voted yet
+            let other = server.create_voter().unwrap();
+            let view = server.get_poll(id.clone(), Some(other)).unwrap();
+            assert_eq!(shows_results(&view), stranger, "{visibility:?}");
+
+            server.close_poll(id.clone(), secret.clone()).unwrap();
+            let view = server.get_poll(id.clone(), None).unwrap();
+            assert_eq!(shows_results(&view), closed, "{visibility:?}");
+            // the admin always sees the results
+            assert!(shows_results(&server.get_poll_admin(id, secret).unwrap()));
+        }
+    }
+
+    #[test]
+    fn results_visible_follows_the_visibility() {
+        let server = server();
+        let id = server.create_poll(new_poll("poll")).unwrap().poll.poll.id;
+        let mut poll = server.load_poll(&id).unwrap();
+        for (visibility, expected) in [
+            (ResultVisibility::Always, [true, true, true]),
+            (ResultVisibility::AfterVoting, [false, true, true]),
+            (ResultVisibility::AfterClosing, [false, false, true]),
+        ] {
+            poll.result_visibility = visibility;
+            poll.closed = false;
+            assert_eq!(results_visible(&poll, false), expected[0]);
+            assert_eq!(results_visible(&poll, true), expected[1]);
+            poll.closed = true;
+            assert_eq!(results_visible(&poll, false), expected[2]);
+        }
+    }
+
+    #[test]
+    fn anonymous_polls_show_aggregates_instead_of_ballots() {
+        let server = server();
+        let poll = CreatePoll {
+            anonymous: true,
+            ..new_poll("poll")
+        };
+        let id = server.create_poll(poll).unwrap().poll.poll.id;
+        let voter = server.create_voter().unwrap();
+        server
+            .vote(id.clone(), ballot(&voter, "voter"), voter.token.clone())
+            .unwrap();
+        let view = anonymize(server.load_poll(&id).unwrap());
+        assert!(view.poll.votes.is_empty());
+        assert!(view.poll.result.is_some());
+        assert_eq!(view.aggregates.unwrap().ballots, 1);
+
+        // the aggregates are results too, hidden ones aren't sent
+        let mut poll = server.load_poll(&id).unwrap();
+        poll.result_visibility = ResultVisibility::AfterClosing;
+        let hidden = public_poll(poll, true);
+        assert!(hidden.results_hidden);
+        assert!(hidden.aggregates.is_none());
+
+        let named = server.create_poll(new_poll("named")).unwrap().poll.poll.id;
+        let view = anonymize(server.load_poll(&named).unwrap());
+        assert!(view.aggregates.is_none());
+    }
+
+    #[test]
+    fn anonymous_polls_reveal_no_voter() {
+        let server = server();
+        let poll = CreatePoll {
+            anonymous: true,
+            seats: 2,
+            ..new_poll("poll")
+        };
+        let created = server.create_poll(poll).unwrap();
+        let (id, secret) = (created.poll.poll.id, created.admin_secret);
+        let alice = server.create_voter().unwrap();
+        let bob = server.create_voter().unwrap();
+        let mut views = vec![];
+        for (voter, name) in [(&alice, "alice"), (&bob, "bob")] {
+            let view = server
+                .vote(id.clone(), ballot(voter, name), voter.token.clone())
+                .unwrap();
+            views.push(view);
+        }
+        // leaves a replaced ballot in the vote history
+        let view = server
+            .update_vote(id.clone(), ballot(&alice, "alice"), alice.token.clone())
+            .unwrap();
+        views.push(view);
+        views.push(server.get_poll(id.clone(), None).unwrap());
+        views.push(server.get_poll(id.clone(), Some(bob.clone())).unwrap());
+        views.push(server.get_poll_admin(id.clone(), secret.clone()).unwrap());
+        views.push(server.close_poll(id, secret).unwrap());
+
+        for view in views {
+            assert!(view.poll.votes.is_empty());
+            assert!(view.poll.vote_history.is_empty());
+            assert!(view.aggregates.is_some());
+            let committee = view.poll.result.as_ref().unwrap().committee.as_ref();
+            let rounds = &committee.unwrap().rounds;
+            assert!(!rounds.is_empty());
+            assert!(rounds.iter().all(|r| r.ballot_weights.is_empty()));
+            let json = serde_json::to_string(&view).unwrap();
+            for secret in ["alice", "bob", alice.user_id.to_str(), bob.user_id.to_str()] {
+                assert!(!json.contains(secret), "{secret} in {json}");
+            }
+        }
+    }
+
+    #[test]
+    fn closed_sessions_drop_their_subscriptions() {
+        let server = server();
+        let id = server.create_poll(new_poll("poll")).unwrap().poll.poll.id;
+        let (sender, _messages) = jsonrpc_core::futures::channel::mpsc::unbounded();
+        let session = Arc::new(Session::new(sender));
+        // assigning an id fails once its receiver is gone
+        let mut receivers = vec![];
+        for _ in 0..2 {
+            let (subscriber, assigned_id, notifications) = Subscriber::new_test("poll_changed");
+            server.subscribe_poll(session.clone(), subscriber, id.clone(), None);
+            receivers.push((assigned_id, notifications));
+        }
+        assert_eq!(server.subscriptions.lock().unwrap().len(), 2);
+        drop(session);
+        assert!(server.subscriptions.lock().unwrap().is_empty());
+    }
+
+    fn list(server: &Server, query: ListPolls) -> (Vec<String>, Option<PublicPollId>) {
+        let page = server.list_polls(query).unwrap();
+        let titles = page.polls.into_iter().map(|p| p.title).collect();
+        (titles, page.next_cursor)
+    }
+
+    /// creates polls with the given titles and returns their ids in id order
+    fn create_polls(server: &Server, titles: &[&str]) -> Vec<(String, PublicPollId)> {
+        let mut polls: Vec<_> = titles
+            .iter()
+            .map(|t| {
+                let id = server.create_poll(new_poll(t)).unwrap().poll.poll.id;
+                (t.to_string(), id)
+            })
+            .collect();
+        polls.sort_by(|a, b| a.1.to_str().cmp(b.1.to_str()));
+        polls
+    }
+
+    #[test]
+    fn list_polls_pages_through_all_polls_in_id_order() {
+        let server = server();
+        let polls = create_polls(&server, &["one", "two", "three", "four", "five"]);
+        let titles = |range: std::ops::Range<usize>| -> Vec<String> {
+            polls[range].iter().map(|(t, _)| t.clone()).collect()
+        };
+        let query = |cursor| ListPolls {
+            cursor,
+            limit: 2,
+            ..Default::default()
+        };
+        let (page, cursor) = list(&server, query(None));
+        assert_eq!(page, titles(0..2));
+        assert_eq!(cursor.as_ref(), Some(&polls[1].1));
+        let (page, cursor) = list(&server, query(cursor));
+        assert_eq!(page, titles(2..4));
+        assert_eq!(cursor.as_ref(), Some(&polls[3].1));
+        let (page, cursor) = list(&server, query(cursor));
+        assert_eq!(page, titles(4..5));
+        assert_eq!(cursor, None);
+
+        // a last page that is exactly full has no cursor either
+        let (page, cursor) = list(&server, query(Some(polls[0].1.clone())));
+        assert_eq!(page, titles(1..3));
+        let (page, cursor) = list(&server, query(cursor));
+        assert_eq!(page, titles(3..5));
+        assert_eq!(cursor, None);
+
+        let (page, _) = list(&server, ListPolls::default());
+        assert_eq!(page.len(), 1, "the limit is at least 1");
+    }
+
+    #[test]
+    fn list_polls_resumes_after_unknown_cursors() {
+        let server = server();
+        let polls = create_polls(&server, &["one", "two", "three"]);
+        let query = |cursor: &PublicPollId| ListPolls {
+            cursor: Some(cursor.clone()),
+            limit: 10,
+            ..Default::default()
+        };
+        // the poll at the cursor was deleted in between
+        let secret = server.store.admin_secret(&polls[1].1).unwrap().unwrap();
+        server.delete_poll(polls[1].1.clone(), secret).unwrap();
+        let (page, cursor) = list(&server, query(&polls[1].1));
+        assert_eq!(page, vec![polls[2].0.clone()]);
+        assert_eq!(cursor, None);
+        // after every id
+        let (page, cursor) = list(&server, query(&PublicPollId::from_str("~")));
+        assert!(page.is_empty());
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn list_polls_filters_by_state_and_title() {
+        let server = server();
+        let polls = create_polls(&server, &["Lunch", "Dinner", "lunch break", "Breakfast"]);
+        for (title, id) in &polls {
+            if title.starts_with('L') || title.starts_with('D') {
+                let secret = server.store.admin_secret(id).unwrap().unwrap();
+                server.close_poll(id.clone(), secret).unwrap();
+            }
+        }
+        let sorted = |mut titles: Vec<String>| {
+            titles.sort();
+            titles
+        };
+        let filter = |state, title: Option<&str>| {
+            let query = ListPolls {
+                limit: 10,
+                state,
+                title_contains: title.map(str::to_string),
+                ..Default::default()
+            };
+            sorted(list(&server, query).0)
+        };
+        assert_eq!(filter(PollStateFilter::Any, None).len(), 4);
+        assert_eq!(
+            filter(PollStateFilter::Open, None),
+            ["Breakfast", "lunch break"]
+        );
+        assert_eq!(filter(PollStateFilter::Closed, None), ["Dinner", "Lunch"]);
+        assert_eq!(
+            filter(PollStateFilter::Any, Some("LUNCH")),
+            ["Lunch", "lunch break"]
+        );
+        assert_eq!(
+            filter(PollStateFilter::Open, Some("break")),
+            ["Breakfast", "lunch break"]
+        );
+        assert!(filter(PollStateFilter::Closed, Some("break")).is_empty());
+
+        // filtered polls don't take up room on a page
+        let mut query = ListPolls {
+            limit: 1,
+            state: PollStateFilter::Closed,
+            ..Default::default()
+        };
+        let mut pages = vec![];
+        loop {
+            let (page, cursor) = list(&server, query.clone());
+            pages.extend(page);
+            match cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
         }
+        assert_eq!(sorted(pages), ["Dinner", "Lunch"]);
     }
 }
@@ -0,0 +1,218 @@
+//! upgrades stored polls when their format changes. every database records the schema version
+//! it was written with and the server refuses to start until `migrate` brought it up to date
+use anyhow::{bail, Context};
+use common::{AdminSecret, Poll, PublicPollId};
+
+use crate::{
+    store::{PollDocument, PollStore},
+    tally,
+};
+
+/// the version written by this build. always the version of the last migration
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// databases that don't record a version were written before versions existed
+const UNVERSIONED: u32 = 0;
+
+struct Migration {
+    /// the schema version of the database afterwards
+    version: u32,
+    description: &'static str,
+    /// rewrites one stored poll. the document doesn't have to match the current types until
+    /// all pending migrations ran
+    upgrade: fn(&mut PollDocument) -> anyhow::Result<()>,
+}
+
+/// in order. a new `Poll` version gets a migration that deserializes the document as `Poll`
+/// and writes `Poll::into_latest` back
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "upgrade polls of the first release to V2 with the default settings, recompute \
+                  their results and give polls without an admin secret one",
+    upgrade: upgrade_to_v2,
+}];
+
+fn upgrade_to_v2(document: &mut PollDocument) -> anyhow::Result<()> {
+    let poll: Poll = serde_json::from_value(document.poll.take())?;
+    let mut poll = poll.into_latest();
+    if poll.result.is_none() {
+        poll.result = Some(tally::compute_vote_result(&poll));
+    }
+    document.poll = serde_json::to_value(Poll::V2(Box::new(poll)))?;
+    if document.admin_secret.is_none() {
+        document.admin_secret = Some(AdminSecret::new(nanoid::nanoid!(32)));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub from: u32,
+    /// descriptions of the migrations that ran, or would run in a dry run
+    pub applied: Vec<&'static str>,
+    pub polls: usize,
+    /// admin secrets generated for polls that had none. they are only shown here
+    pub new_admin_secrets: Vec<(PublicPollId, AdminSecret)>,
+}
+
+/// brings the store to `SCHEMA_VERSION`. all polls are upgraded before anything is written,
+/// so a poll that can't be upgraded leaves the database untouched
+pub fn migrate(store: &dyn PollStore, dry_run: bool) -> anyhow::Result<MigrationReport> {
+    let from = store.schema_version()?.unwrap_or(UNVERSIONED);
+    if from > SCHEMA_VERSION {
+        bail!("database has schema version {from}, but this build only knows {SCHEMA_VERSION}");
+    }
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > from).collect();
+    let mut report = MigrationReport {
+        from,
+        applied: pending.iter().map(|m| m.description).collect(),
+        ..Default::default()
+    };
+    if pending.is_empty() {
+        return Ok(report);
+    }
+    let mut upgraded = vec![];
+    for mut document in store.poll_documents()? {
+        let had_secret = document.admin_secret.is_some();
+        for migration in &pending {
+            (migration.upgrade)(&mut document).with_context(|| {
+                format!(
+                    "could not migrate poll {} to version {}",
+                    document.id.to_str(),
+                    migration.version
+                )
+            })?;
+        }
+        let poll: Poll = serde_json::from_value(document.poll).with_context(|| {
+            format!(
+                "poll {} doesn't match the current format",
+                document.id.to_str()
+            )
+        })?;
+        let secret = document
+            .admin_secret
+            .with_context(|| format!("poll {} has no admin secret", document.id.to_str()))?;
+        if !had_secret {
+            report
+                .new_admin_secrets
+                .push((document.id.clone(), secret.clone()));
+        }
+        upgraded.push((poll.into_latest(), secret));
+    }
+    report.polls = upgraded.len();
+    if !dry_run {
+        for (poll, secret) in &upgraded {
+            store.insert_poll(poll, secret)?;
+        }
+        store.set_schema_version(SCHEMA_VERSION)?;
+    }
+    Ok(report)
+}
+
+/// fails unless the store is at the version this build writes
+pub fn check_schema_version(store: &dyn PollStore) -> anyhow::Result<()> {
+    let version = store.schema_version()?.unwrap_or(UNVERSIONED);
+    if version < SCHEMA_VERSION {
+        bail!(
+            "database has schema version {version}, but this build needs {SCHEMA_VERSION}. \
+             back it up and run the migrate command"
+        );
+    }
+    if version > SCHEMA_VERSION {
+        bail!("database has schema version {version}, which is newer than this build");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{PollOption, PollOptionId, PollV1, PublicUserId, ScoreScale, ScoreVote};
+
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn id(id: &str) -> PollOptionId {
+        PollOptionId::from_str(id.to_string())
+    }
+
+    /// a poll as the first release stored it, without an admin secret
+    fn legacy_store() -> MemoryStore {
+        let option = |option: &str| PollOption {
+            id: id(option),
+            title: option.to_string(),
+            description_text_markdown: String::new(),
+        };
+        let ballot = |voter: &str, a: Option<f64>, b: Option<f64>| ScoreVote {
+            user_id: PublicUserId::from_str(voter),
+            user_name: voter.to_string(),
+            votes: [(id("a"), a), (id("b"), b)].into(),
+        };
+        let store = MemoryStore::default();
+        store.insert_document(
+            Poll::V1(PollV1 {
+                id: PublicPollId::from_str("old"),
+                title: "Lunch".to_string(),
+                description_text_markdown: String::new(),
+                options: vec![option("a"), option("b")],
+                votes: vec![
+                    ballot("ann", Some(2.0), Some(8.0)),
+                    ballot("bob", None, Some(6.0)),
+                ],
+                result: Some([(id("a"), Some(2.0)), (id("b"), Some(7.0))].into()),
+            }),
+            None,
+        );
+        store
+    }
+
+    #[test]
+    fn upgrades_a_poll_of_the_first_release() {
+        let store = legacy_store();
+        check_schema_version(&store).unwrap_err();
+        let report = migrate(&store, false).unwrap();
+        assert_eq!(report.from, UNVERSIONED);
+        assert_eq!(report.applied.len(), 1);
+        assert_eq!(report.polls, 1);
+        check_schema_version(&store).unwrap();
+
+        let poll_id = PublicPollId::from_str("old");
+        let poll = store.get_poll(&poll_id).unwrap().unwrap();
+        assert_eq!(poll.votes.len(), 2);
+        assert_eq!(poll.score_scale, ScoreScale::default());
+        assert_eq!(poll.seats, 1);
+        assert!(!poll.closed);
+        let result = poll.result.unwrap();
+        assert_eq!(result.ranking, vec![id("b"), id("a")]);
+        assert_eq!(result.scores[&id("a")], Some(2.0));
+
+        let (secret_of, secret) = &report.new_admin_secrets[0];
+        assert_eq!(secret_of, &poll_id);
+        assert_eq!(store.admin_secret(&poll_id).unwrap().as_ref(), Some(secret));
+        // the document is stored in the latest version
+        assert!(store.poll_documents().unwrap()[0].poll.get("V2").is_some());
+    }
+
+    #[test]
+    fn dry_run_leaves_the_store_untouched() {
+        let store = legacy_store();
+        let report = migrate(&store, true).unwrap();
+        assert_eq!(report.polls, 1);
+        assert_eq!(report.new_admin_secrets.len(), 1);
+        assert_eq!(store.schema_version().unwrap(), None);
+        assert!(store.poll_documents().unwrap()[0].poll.get("V1").is_some());
+        assert_eq!(
+            store.admin_secret(&PublicPollId::from_str("old")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn does_nothing_at_the_current_version() {
+        let store = legacy_store();
+        migrate(&store, false).unwrap();
+        let report = migrate(&store, false).unwrap();
+        assert_eq!(report.from, SCHEMA_VERSION);
+        assert!(report.applied.is_empty());
+        assert!(report.new_admin_secrets.is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+//! serves the built frontend on the same origin as the HTTP endpoint, so a deployment only
+//! needs the backend
+use std::path::{Path, PathBuf};
+
+use jsonrpc_http_server::{
+    hyper::{header, http, Body, Method, Request, Response, StatusCode},
+    tokio, RequestMiddleware, RequestMiddlewareAction,
+};
+
+pub struct StaticFiles {
+    /// the output directory of `trunk build`
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    /// fails if `root` doesn't contain an index.html
+    pub fn new(root: PathBuf) -> anyhow::Result<StaticFiles> {
+        if !root.join("index.html").is_file() {
+            anyhow::bail!(
+                "{} has no index.html, build the frontend with `trunk build` first",
+                root.display()
+            );
+        }
+        Ok(StaticFiles { root })
+    }
+}
+
+/// the file under `root` to answer a request for `path` with. paths without a file extension that
+/// don't exist are routes of the frontend, like /poll/<id>, and get the index.html. the path is
+/// never percent-decoded, so an encoded ".." is only a name that doesn't exist
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut file = root.to_path_buf();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        if segment == ".." || segment.contains('\\') {
+            return None;
+        }
+        file.push(segment);
+    }
+    if file.is_file() {
+        return Some(file);
+    }
+    let is_route = path
+        .rsplit('/')
+        .next()
+        .is_none_or(|last| !last.contains('.'));
+    is_route.then(|| root.join("index.html"))
+}
+
+impl RequestMiddleware for StaticFiles {
+    fn on_request(&self, request: Request<Body>) -> RequestMiddlewareAction {
+        // RPC calls are POSTs, CORS preflights are OPTIONS
+        if request.method() != Method::GET && request.method() != Method::HEAD {
+            return RequestMiddlewareAction::Proceed {
+                should_continue_on_invalid_cors: false,
+                request,
+            };
+        }
+        let root = self.root.clone();
+        let path = request.uri().path().to_string();
+        let head = request.method() == Method::HEAD;
+        RequestMiddlewareAction::Respond {
+            should_validate_hosts: true,
+            response: Box::pin(async move {
+                // the server threads also answer RPC calls, so they don't wait for the disk
+                let response =
+                    tokio::task::spawn_blocking(move || respond(resolve(&root, &path), head))
+                        .await
+                        .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR));
+                Ok(response.expect("headers are valid"))
+            }),
+        }
+    }
+}
+
+/// the response to a GET request for `file`, or only its headers for a HEAD request
+fn respond(file: Option<PathBuf>, head: bool) -> Result<Response<Body>, http::Error> {
+    let Some(file) = file else {
+        return status(StatusCode::NOT_FOUND);
+    };
+    let content = if head {
+        std::fs::metadata(&file).map(|m| (m.len(), Body::empty()))
+    } else {
+        std::fs::read(&file).map(|content| (content.len() as u64, Body::from(content)))
+    };
+    let Ok((length, body)) = content else {
+        return status(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type(&file))
+        .header(header::CONTENT_LENGTH, length)
+        // the index.html references the hashed names of the current build
+        .header(
+            header::CACHE_CONTROL,
+            if file.ends_with("index.html") {
+                "no-cache"
+            } else {
+                "public, max-age=3600"
+            },
+        )
+        .body(body)
+}
+
+fn status(code: StatusCode) -> Result<Response<Body>, http::Error> {
+    Response::builder().status(code).body(Body::empty())
+}
+
+fn content_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "application/javascript",
+        // needed for WebAssembly.instantiateStreaming
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonrpc_http_server::hyper::body::HttpBody;
+
+    use super::*;
+
+    /// a build output under `dir/dist`, next to a `dir/secret.txt` that must not be served
+    struct Site {
+        dir: PathBuf,
+        root: PathBuf,
+    }
+
+    impl Site {
+        fn new() -> Site {
+            let dir = std::env::temp_dir().join(format!("static-files-test-{}", nanoid::nanoid!()));
+            let root = dir.join("dist");
+            std::fs::create_dir_all(root.join("assets")).unwrap();
+            std::fs::write(root.join("index.html"), "<html></html>").unwrap();
+            std::fs::write(root.join("assets").join("app.js"), "main()").unwrap();
+            std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+            Site { dir, root }
+        }
+    }
+
+    impl Drop for Site {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn serves_existing_files() {
+        let site = Site::new();
+        assert_eq!(
+            resolve(&site.root, "/assets/app.js"),
+            Some(site.root.join("assets").join("app.js"))
+        );
+        assert_eq!(resolve(&site.root, "/assets/missing.js"), None);
+    }
+
+    #[test]
+    fn routes_get_the_index_html() {
+        let site = Site::new();
+        let index = Some(site.root.join("index.html"));
+        assert_eq!(resolve(&site.root, "/"), index);
+        assert_eq!(resolve(&site.root, "/poll/abc"), index);
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        let site = Site::new();
+        assert_eq!(resolve(&site.root, "/../secret.txt"), None);
+        assert_eq!(resolve(&site.root, "/assets/../../secret.txt"), None);
+        assert_eq!(resolve(&site.root, "/..\\secret.txt"), None);
+    }
+
+    #[test]
+    fn encoded_traversal_is_not_decoded() {
+        let site = Site::new();
+        assert_eq!(resolve(&site.root, "/%2e%2e/secret.txt"), None);
+        assert_eq!(resolve(&site.root, "/..%2fsecret.txt"), None);
+        // without an extension it's a route of the frontend
+        assert_eq!(
+            resolve(&site.root, "/%2e%2e/%2e%2e/etc/passwd"),
+            Some(site.root.join("index.html"))
+        );
+    }
+
+    #[test]
+    fn head_responses_have_no_body() {
+        let site = Site::new();
+        let file = site.root.join("assets").join("app.js");
+
+        let get = respond(Some(file.clone()), false).unwrap();
+        assert!(!get.body().is_end_stream());
+        let head = respond(Some(file), true).unwrap();
+        assert!(head.body().is_end_stream());
+        for response in [&get, &head] {
+            assert_eq!(response.headers()[header::CONTENT_LENGTH], "6");
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                "application/javascript"
+            );
+        }
+    }
+}
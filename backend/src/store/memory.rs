@@ -0,0 +1,153 @@
+//! keeps everything in memory, for tests and throwaway instances
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    sync::Mutex,
+};
+
+use common::{
+    AdminSecret, ApiError, Poll, PollSummary, PollV2, PublicPollId, PublicUserId, VoterToken,
+};
+
+use super::{poll_summary, PollDocument, PollStore, StorageContext};
+
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    /// keyed by the poll id, so that listing polls follows the id order like the other stores.
+    /// kept in the version they were written in, like the other stores do
+    polls: BTreeMap<String, Poll>,
+    admin_secrets: HashMap<String, AdminSecret>,
+    voters: HashMap<String, VoterToken>,
+    schema_version: Option<u32>,
+}
+
+impl MemoryStore {
+    /// stores a poll as an older version would have written it, to test migrations
+    #[cfg(test)]
+    pub fn insert_document(&self, poll: Poll, secret: Option<AdminSecret>) {
+        let mut data = self.data.lock().unwrap();
+        let id = poll.id().to_str().to_string();
+        if let Some(secret) = secret {
+            data.admin_secrets.insert(id.clone(), secret);
+        }
+        data.polls.insert(id, poll);
+    }
+}
+
+impl PollStore for MemoryStore {
+    fn get_poll(&self, id: &PublicPollId) -> Result<Option<PollV2>, ApiError> {
+        let data = self.data.lock().unwrap();
+        Ok(data.polls.get(id.to_str()).cloned().map(Poll::into_latest))
+    }
+
+    fn insert_poll(&self, poll: &PollV2, secret: &AdminSecret) -> Result<(), ApiError> {
+        let mut data = self.data.lock().unwrap();
+        let id = poll.id.to_str().to_string();
+        data.admin_secrets.insert(id.clone(), secret.clone());
+        data.polls.insert(id, Poll::V2(Box::new(poll.clone())));
+        Ok(())
+    }
+
+    fn update_poll(
+        &self,
+        id: &PublicPollId,
+        f: &dyn Fn(&mut PollV2) -> Result<(), ApiError>,
+    ) -> Result<PollV2, ApiError> {
+        let mut data = self.data.lock().unwrap();
+        let stored = data
+            .polls
+            .get_mut(id.to_str())
+            .ok_or(ApiError::PollNotFound)?;
+        // work on a copy so that a failing `f` leaves the stored poll untouched
+        let mut poll = stored.clone().into_latest();
+        f(&mut poll)?;
+        *stored = Poll::V2(Box::new(poll.clone()));
+        Ok(poll)
+    }
+
+    fn delete_poll(&self, id: &PublicPollId) -> Result<(), ApiError> {
+        let mut data = self.data.lock().unwrap();
+        data.polls.remove(id.to_str());
+        data.admin_secrets.remove(id.to_str());
+        Ok(())
+    }
+
+    fn admin_secret(&self, poll_id: &PublicPollId) -> Result<Option<AdminSecret>, ApiError> {
+        Ok(self
+            .data
+            .lock()
+            .unwrap()
+            .admin_secrets
+            .get(poll_id.to_str())
+            .cloned())
+    }
+
+    fn visit_poll_summaries(
+        &self,
+        after: Option<&PublicPollId>,
+        visit: &mut dyn FnMut(PollSummary) -> bool,
+    ) -> Result<(), ApiError> {
+        let data = self.data.lock().unwrap();
+        let start = match after {
+            Some(after) => Bound::Excluded(after.to_str()),
+            None => Bound::Unbounded,
+        };
+        for poll in data
+            .polls
+            .range::<str, _>((start, Bound::Unbounded))
+            .map(|(_, p)| p.clone().into_latest())
+        {
+            if !visit(poll_summary(&poll)) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_voter(&self, user_id: &PublicUserId, token: &VoterToken) -> Result<(), ApiError> {
+        self.data
+            .lock()
+            .unwrap()
+            .voters
+            .insert(user_id.to_str().to_string(), token.clone());
+        Ok(())
+    }
+
+    fn voter_token(&self, user_id: &PublicUserId) -> Result<Option<VoterToken>, ApiError> {
+        Ok(self
+            .data
+            .lock()
+            .unwrap()
+            .voters
+            .get(user_id.to_str())
+            .cloned())
+    }
+
+    fn schema_version(&self) -> Result<Option<u32>, ApiError> {
+        Ok(self.data.lock().unwrap().schema_version)
+    }
+
+    fn set_schema_version(&self, version: u32) -> Result<(), ApiError> {
+        self.data.lock().unwrap().schema_version = Some(version);
+        Ok(())
+    }
+
+    fn poll_documents(&self) -> Result<Vec<PollDocument>, ApiError> {
+        let data = self.data.lock().unwrap();
+        data.polls
+            .iter()
+            .map(|(id, poll)| {
+                Ok(PollDocument {
+                    id: poll.id().clone(),
+                    poll: serde_json::to_value(poll).storage("serializing")?,
+                    admin_secret: data.admin_secrets.get(id).cloned(),
+                })
+            })
+            .collect()
+    }
+}
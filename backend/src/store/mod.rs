@@ -0,0 +1,255 @@
+//! persistence of polls, admin secrets and voter tokens
+mod memory;
+mod sled_store;
+mod sqlite;
+
+use std::sync::Arc;
+
+use common::{AdminSecret, ApiError, PollSummary, PollV2, PublicPollId, PublicUserId, VoterToken};
+
+use crate::{
+    config::{Config, StorageKind},
+    migrations::SCHEMA_VERSION,
+};
+pub use memory::MemoryStore;
+pub use sled_store::SledStore;
+pub use sqlite::SqliteStore;
+
+pub trait PollStore: Send + Sync {
+    fn get_poll(&self, id: &PublicPollId) -> Result<Option<PollV2>, ApiError>;
+
+    /// stores a new poll together with its admin secret
+    fn insert_poll(&self, poll: &PollV2, secret: &AdminSecret) -> Result<(), ApiError>;
+
+    /// applies `f` to the stored poll atomically and returns the new state.
+    /// `f` may be called several times if concurrent updates conflict
+    fn update_poll(
+        &self,
+        id: &PublicPollId,
+        f: &dyn Fn(&mut PollV2) -> Result<(), ApiError>,
+    ) -> Result<PollV2, ApiError>;
+
+    /// removes the poll and its admin secret
+    fn delete_poll(&self, id: &PublicPollId) -> Result<(), ApiError>;
+
+    fn admin_secret(&self, poll_id: &PublicPollId) -> Result<Option<AdminSecret>, ApiError>;
+
+    /// calls `visit` with the summaries of the polls whose id comes after `after`, in id order,
+    /// until it returns false. `visit` must not call back into the store
+    fn visit_poll_summaries(
+        &self,
+        after: Option<&PublicPollId>,
+        visit: &mut dyn FnMut(PollSummary) -> bool,
+    ) -> Result<(), ApiError>;
+
+    fn insert_voter(&self, user_id: &PublicUserId, token: &VoterToken) -> Result<(), ApiError>;
+
+    fn voter_token(&self, user_id: &PublicUserId) -> Result<Option<VoterToken>, ApiError>;
+
+    /// the version of the stored data, see `migrations`. `None` for databases written before
+    /// versions were recorded
+    fn schema_version(&self) -> Result<Option<u32>, ApiError>;
+
+    fn set_schema_version(&self, version: u32) -> Result<(), ApiError>;
+
+    /// every stored poll without deserializing it into the current types, for migrations
+    fn poll_documents(&self) -> Result<Vec<PollDocument>, ApiError>;
+}
+
+/// a stored poll as migrations see it
+#[derive(Debug)]
+pub struct PollDocument {
+    pub id: PublicPollId,
+    /// the serialized `Poll`, in whatever shape it was written
+    pub poll: serde_json::Value,
+    /// polls from before admin secrets existed have none
+    pub admin_secret: Option<AdminSecret>,
+}
+
+/// opens the store selected in the config. new databases start at the current schema version
+pub fn open(config: &Config) -> Result<Arc<dyn PollStore>, ApiError> {
+    let store: Arc<dyn PollStore> = match config.storage {
+        StorageKind::Sled => Arc::new(SledStore::open(&config.database)?),
+        StorageKind::Sqlite => Arc::new(SqliteStore::open(&config.database)?),
+        StorageKind::Memory => Arc::new(MemoryStore::default()),
+    };
+    if store.schema_version()?.is_none() && store.poll_documents()?.is_empty() {
+        store.set_schema_version(SCHEMA_VERSION)?;
+    }
+    Ok(store)
+}
+
+/// what the stores keep next to every poll so that listing polls doesn't load the ballots
+pub fn poll_summary(poll: &PollV2) -> PollSummary {
+    PollSummary {
+        id: poll.id.clone(),
+        title: poll.title.clone(),
+        closed: poll.closed,
+        opens_at: poll.opens_at,
+        closes_at: poll.closes_at,
+        ballots: poll.votes.len(),
+    }
+}
+
+/// turns database and serialization failures into `ApiError::StorageError`
+trait StorageContext<T> {
+    fn storage(self, what: &str) -> Result<T, ApiError>;
+}
+
+impl<T, E: std::fmt::Display> StorageContext<T> for Result<T, E> {
+    fn storage(self, what: &str) -> Result<T, ApiError> {
+        self.map_err(|e| ApiError::StorageError {
+            message: format!("{what}: {e}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use common::PollV1;
+
+    use super::*;
+
+    /// a directory for a database that is removed again when the test ends
+    pub(super) struct TempDir(pub PathBuf);
+
+    impl TempDir {
+        pub fn new() -> TempDir {
+            TempDir(std::env::temp_dir().join(format!("store-test-{}", nanoid::nanoid!())))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn poll(id: &str) -> PollV2 {
+        PollV2::from(PollV1 {
+            id: PublicPollId::from_str(id),
+            title: format!("poll {id}"),
+            description_text_markdown: String::new(),
+            options: vec![],
+            votes: vec![],
+            result: None,
+        })
+    }
+
+    fn secret(id: &str) -> AdminSecret {
+        AdminSecret::new(format!("secret of {id}"))
+    }
+
+    fn listed(store: &dyn PollStore, after: Option<&str>, limit: usize) -> Vec<String> {
+        let after = after.map(PublicPollId::from_str);
+        let mut ids = vec![];
+        store
+            .visit_poll_summaries(after.as_ref(), &mut |summary| {
+                ids.push(summary.id.to_str().to_string());
+                ids.len() < limit
+            })
+            .unwrap();
+        ids
+    }
+
+    /// what every store has to do the same way
+    fn check_store(store: &dyn PollStore) {
+        // the lengths differ from the id order, so an index keyed by something that starts with
+        // the length lists them out of order
+        let ids = ["b", "aa", "c", "a", "ab"];
+        for id in ids {
+            store.insert_poll(&poll(id), &secret(id)).unwrap();
+        }
+        let missing = PublicPollId::from_str("missing");
+        assert!(store.get_poll(&missing).unwrap().is_none());
+        assert_eq!(store.admin_secret(&missing).unwrap(), None);
+        let b = PublicPollId::from_str("b");
+        assert_eq!(store.get_poll(&b).unwrap().unwrap().title, "poll b");
+        assert_eq!(store.admin_secret(&b).unwrap(), Some(secret("b")));
+
+        assert_eq!(listed(store, None, 10), ["a", "aa", "ab", "b", "c"]);
+        assert_eq!(listed(store, None, 2), ["a", "aa"]);
+        assert_eq!(listed(store, Some("aa"), 10), ["ab", "b", "c"]);
+        // the cursor doesn't have to exist
+        assert_eq!(listed(store, Some("ac"), 10), ["b", "c"]);
+        assert!(listed(store, Some("d"), 10).is_empty());
+
+        let updated = store
+            .update_poll(&b, &|poll| {
+                poll.title = "renamed".to_string();
+                poll.closed = true;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(updated.title, "renamed");
+        assert!(store.get_poll(&b).unwrap().unwrap().closed);
+        let mut summary = None;
+        store
+            .visit_poll_summaries(Some(&PublicPollId::from_str("ab")), &mut |s| {
+                summary = Some(s);
+                false
+            })
+            .unwrap();
+        let summary = summary.unwrap();
+        assert_eq!((summary.title.as_str(), summary.closed), ("renamed", true));
+        // a failing update changes nothing
+        let failed = store.update_poll(&b, &|poll| {
+            poll.title = "lost".to_string();
+            Err(ApiError::PollClosed)
+        });
+        assert_eq!(failed.unwrap_err(), ApiError::PollClosed);
+        assert_eq!(store.get_poll(&b).unwrap().unwrap().title, "renamed");
+        let not_found = store.update_poll(&missing, &|_| Ok(()));
+        assert_eq!(not_found.unwrap_err(), ApiError::PollNotFound);
+
+        store.delete_poll(&b).unwrap();
+        assert!(store.get_poll(&b).unwrap().is_none());
+        assert_eq!(store.admin_secret(&b).unwrap(), None);
+        assert_eq!(listed(store, None, 10), ["a", "aa", "ab", "c"]);
+
+        let mut documents: Vec<_> = store
+            .poll_documents()
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.id.to_str().to_string(), d.admin_secret))
+            .collect();
+        documents.sort_by(|a, b| a.0.cmp(&b.0));
+        let expected: Vec<_> = ["a", "aa", "ab", "c"]
+            .into_iter()
+            .map(|id| (id.to_string(), Some(secret(id))))
+            .collect();
+        assert_eq!(documents, expected);
+
+        let voter = PublicUserId::from_str("voter");
+        assert_eq!(store.voter_token(&voter).unwrap(), None);
+        store
+            .insert_voter(&voter, &VoterToken::new("token"))
+            .unwrap();
+        assert_eq!(
+            store.voter_token(&voter).unwrap(),
+            Some(VoterToken::new("token"))
+        );
+
+        assert_eq!(store.schema_version().unwrap(), None);
+        store.set_schema_version(SCHEMA_VERSION).unwrap();
+        assert_eq!(store.schema_version().unwrap(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn memory_store_conforms() {
+        check_store(&MemoryStore::default());
+    }
+
+    #[test]
+    fn sled_store_conforms() {
+        let dir = TempDir::new();
+        check_store(&SledStore::open(&dir.0).unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_conforms() {
+        check_store(&SqliteStore::open(Path::new(":memory:")).unwrap());
+    }
+}
@@ -0,0 +1,312 @@
+//! the default store. every tree is keyed by the CBOR encoded id, except for the poll index.
+//! CBOR puts the length of an id before it, so the poll index uses the plain id to list polls
+//! in id order like the other stores
+use std::path::Path;
+
+use common::{
+    AdminSecret, ApiError, Poll, PollSummary, PollV2, PublicPollId, PublicUserId, VoterToken,
+};
+use sled::{
+    transaction::{
+        ConflictableTransactionError::Abort, ConflictableTransactionResult, TransactionError,
+        TransactionalTree,
+    },
+    Transactional,
+};
+
+use super::{poll_summary, PollDocument, PollStore, StorageContext};
+
+pub struct SledStore {
+    /// CBOR encoded `Poll`s
+    polls: sled::Tree,
+    /// `PollSummary` of every poll, so that listing polls doesn't load the ballots. keyed by
+    /// `index_key`
+    poll_index: sled::Tree,
+    admin_secrets: sled::Tree,
+    voters: sled::Tree,
+    /// the schema version under `SCHEMA_VERSION_KEY`
+    meta: sled::Tree,
+}
+
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+impl SledStore {
+    pub fn open(path: &Path) -> Result<SledStore, ApiError> {
+        let db = sled::open(path).storage("opening database")?;
+        let open_tree = |name: &str| db.open_tree(name).storage("opening database");
+        let store = SledStore {
+            polls: open_tree("polls")?,
+            poll_index: open_tree("poll_index")?,
+            admin_secrets: open_tree("admin_secrets")?,
+            voters: open_tree("voters")?,
+            meta: open_tree("meta")?,
+        };
+        store.rebuild_poll_index()?;
+        Ok(store)
+    }
+
+    /// fills the "poll_index" tree from scratch if it's out of sync with the "polls" tree,
+    /// e.g. for databases created before the index existed or while it was keyed by the CBOR
+    /// encoded id. polls in an outdated format are left out, `migrate` indexes them when it
+    /// rewrites them
+    fn rebuild_poll_index(&self) -> Result<(), ApiError> {
+        if self.poll_index_is_current()? {
+            return Ok(());
+        }
+        self.poll_index.clear().storage("clearing poll index")?;
+        for entry in self.polls.iter() {
+            let (_k, v) = entry.storage("loading")?;
+            let Ok(poll) = serde_cbor::from_slice::<Poll>(&v) else {
+                continue;
+            };
+            let poll = poll.into_latest();
+            self.poll_index
+                .insert(
+                    index_key(&poll.id),
+                    serde_cbor::to_vec(&poll_summary(&poll)).storage("serializing")?,
+                )
+                .storage("inserting into db")?;
+        }
+        Ok(())
+    }
+
+    /// whether the index has an entry per poll under the current key
+    fn poll_index_is_current(&self) -> Result<bool, ApiError> {
+        if self.poll_index.len() != self.polls.len() {
+            return Ok(false);
+        }
+        match self.poll_index.first().storage("loading")? {
+            Some((k, v)) => {
+                let summary: PollSummary = serde_cbor::from_slice(&v).storage("deserializing")?;
+                Ok(*k == *index_key(&summary.id))
+            }
+            None => Ok(true),
+        }
+    }
+}
+
+/// the key of a poll in the poll index
+fn index_key(id: &PublicPollId) -> &[u8] {
+    id.to_str().as_bytes()
+}
+
+/// the error that aborted a transaction, or the storage error that failed it
+fn transaction_error(e: TransactionError<ApiError>) -> ApiError {
+    match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => ApiError::StorageError {
+            message: format!("error in transaction: {e}"),
+        },
+    }
+}
+
+impl PollStore for SledStore {
+    fn get_poll(&self, id: &PublicPollId) -> Result<Option<PollV2>, ApiError> {
+        let id = serde_cbor::to_vec(id).storage("serializing")?;
+        match self.polls.get(&id).storage("loading")? {
+            Some(poll_ser) => {
+                let poll = serde_cbor::from_slice::<Poll>(&poll_ser).storage("deserializing")?;
+                Ok(Some(poll.into_latest()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn insert_poll(&self, poll: &PollV2, secret: &AdminSecret) -> Result<(), ApiError> {
+        let id_ser = serde_cbor::to_vec(&poll.id).storage("serializing")?;
+        let secret_ser = serde_cbor::to_vec(secret).storage("serializing")?;
+        let summary_ser = serde_cbor::to_vec(&poll_summary(poll)).storage("serializing")?;
+        let poll_ser =
+            serde_cbor::to_vec(&Poll::V2(Box::new(poll.clone()))).storage("serializing")?;
+        (&self.polls, &self.admin_secrets, &self.poll_index)
+            .transaction(|(polls, admin_secrets, index)| {
+                admin_secrets.insert(id_ser.as_slice(), secret_ser.as_slice())?;
+                index.insert(index_key(&poll.id), summary_ser.as_slice())?;
+                polls.insert(id_ser.as_slice(), poll_ser.as_slice())?;
+                Ok(())
+            })
+            .map_err(transaction_error)
+    }
+
+    fn update_poll(
+        &self,
+        id: &PublicPollId,
+        f: &dyn Fn(&mut PollV2) -> Result<(), ApiError>,
+    ) -> Result<PollV2, ApiError> {
+        (&self.polls, &self.poll_index)
+            .transaction(
+                |(polls, index): &(TransactionalTree, TransactionalTree)| -> ConflictableTransactionResult<PollV2, ApiError> {
+                    let id_ser = serde_cbor::to_vec(id)
+                        .storage("serializing")
+                        .map_err(Abort)?;
+                    let poll_ser = polls
+                        .get(&id_ser)
+                        .storage("loading")
+                        .map_err(Abort)?
+                        .ok_or(Abort(ApiError::PollNotFound))?;
+                    let mut poll = serde_cbor::from_slice::<Poll>(&poll_ser)
+                        .storage("deserializing")
+                        .map_err(Abort)?
+                        .into_latest();
+                    f(&mut poll).map_err(Abort)?;
+                    let summary = serde_cbor::to_vec(&poll_summary(&poll))
+                        .storage("serializing")
+                        .map_err(Abort)?;
+                    index.insert(index_key(id), summary)?;
+                    let ser = serde_cbor::to_vec(&Poll::V2(Box::new(poll.clone())))
+                        .storage("serializing")
+                        .map_err(Abort)?;
+                    polls.insert(id_ser, ser)?;
+                    Ok(poll)
+                },
+            )
+            .map_err(transaction_error)
+    }
+
+    fn delete_poll(&self, id: &PublicPollId) -> Result<(), ApiError> {
+        let id_ser = serde_cbor::to_vec(id).storage("serializing")?;
+        (&self.polls, &self.admin_secrets, &self.poll_index)
+            .transaction(|(polls, admin_secrets, index)| {
+                for tree in [polls, admin_secrets] {
+                    tree.remove(id_ser.as_slice())?;
+                }
+                index.remove(index_key(id))?;
+                Ok(())
+            })
+            .map_err(transaction_error)
+    }
+
+    fn admin_secret(&self, poll_id: &PublicPollId) -> Result<Option<AdminSecret>, ApiError> {
+        let id = serde_cbor::to_vec(poll_id).storage("serializing")?;
+        match self.admin_secrets.get(id).storage("loading")? {
+            Some(stored) => Ok(Some(
+                serde_cbor::from_slice(&stored).storage("deserializing")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn visit_poll_summaries(
+        &self,
+        after: Option<&PublicPollId>,
+        visit: &mut dyn FnMut(PollSummary) -> bool,
+    ) -> Result<(), ApiError> {
+        let entries = match after {
+            Some(after) => self.poll_index.range(index_key(after)..),
+            None => self.poll_index.iter(),
+        };
+        for entry in entries {
+            let (_k, v) = entry.storage("loading")?;
+            let summary: PollSummary = serde_cbor::from_slice(&v).storage("deserializing")?;
+            if after.is_some_and(|after| &summary.id == after) {
+                continue;
+            }
+            if !visit(summary) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_voter(&self, user_id: &PublicUserId, token: &VoterToken) -> Result<(), ApiError> {
+        self.voters
+            .insert(
+                serde_cbor::to_vec(user_id).storage("serializing")?,
+                serde_cbor::to_vec(token).storage("serializing")?,
+            )
+            .storage("inserting into db")?;
+        Ok(())
+    }
+
+    fn voter_token(&self, user_id: &PublicUserId) -> Result<Option<VoterToken>, ApiError> {
+        let id = serde_cbor::to_vec(user_id).storage("serializing")?;
+        match self.voters.get(id).storage("loading")? {
+            Some(stored) => Ok(Some(
+                serde_cbor::from_slice(&stored).storage("deserializing")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn schema_version(&self) -> Result<Option<u32>, ApiError> {
+        match self.meta.get(SCHEMA_VERSION_KEY).storage("loading")? {
+            Some(version) => Ok(Some(
+                serde_cbor::from_slice(&version).storage("deserializing")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn set_schema_version(&self, version: u32) -> Result<(), ApiError> {
+        self.meta
+            .insert(
+                SCHEMA_VERSION_KEY,
+                serde_cbor::to_vec(&version).storage("serializing")?,
+            )
+            .storage("inserting into db")?;
+        Ok(())
+    }
+
+    fn poll_documents(&self) -> Result<Vec<PollDocument>, ApiError> {
+        let mut documents = vec![];
+        for entry in self.polls.iter() {
+            let (k, v) = entry.storage("loading")?;
+            let id: PublicPollId = serde_cbor::from_slice(&k).storage("deserializing")?;
+            let admin_secret = self.admin_secret(&id)?;
+            documents.push(PollDocument {
+                id,
+                poll: serde_cbor::from_slice(&v).storage("deserializing")?,
+                admin_secret,
+            });
+        }
+        Ok(documents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::PollV1;
+
+    use super::*;
+    use crate::store::tests::TempDir;
+
+    #[test]
+    fn an_index_keyed_by_cbor_ids_is_rebuilt() {
+        let dir = TempDir::new();
+        let store = SledStore::open(&dir.0).unwrap();
+        for id in ["b", "aa"] {
+            let poll = PollV2::from(PollV1 {
+                id: PublicPollId::from_str(id),
+                title: id.to_string(),
+                description_text_markdown: String::new(),
+                options: vec![],
+                votes: vec![],
+                result: None,
+            });
+            store
+                .insert_poll(&poll, &AdminSecret::new("secret"))
+                .unwrap();
+        }
+        // the index as earlier versions wrote it
+        let summaries: Vec<_> = store.poll_index.iter().map(Result::unwrap).collect();
+        store.poll_index.clear().unwrap();
+        for (_k, summary) in summaries {
+            let id = serde_cbor::from_slice::<PollSummary>(&summary).unwrap().id;
+            let key = serde_cbor::to_vec(&id).unwrap();
+            store.poll_index.insert(key, summary).unwrap();
+        }
+
+        // what `open` does
+        store.rebuild_poll_index().unwrap();
+        let keys: Vec<_> = store.poll_index.iter().keys().map(Result::unwrap).collect();
+        assert_eq!(keys, [b"aa".as_slice(), b"b".as_slice()]);
+        let mut titles = vec![];
+        store
+            .visit_poll_summaries(None, &mut |summary| {
+                titles.push(summary.title);
+                true
+            })
+            .unwrap();
+        assert_eq!(titles, ["aa", "b"]);
+    }
+}
@@ -0,0 +1,257 @@
+//! stores polls in a single SQLite file. the polls are kept as JSON next to the columns
+//! needed for listing them
+use std::{path::Path, sync::Mutex};
+
+use common::{
+    AdminSecret, ApiError, Poll, PollSummary, PollV2, PublicPollId, PublicUserId, VoterToken,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{poll_summary, PollDocument, PollStore, StorageContext};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS polls (
+    id TEXT PRIMARY KEY NOT NULL,
+    -- the JSON encoded `Poll`
+    poll TEXT NOT NULL,
+    title TEXT NOT NULL,
+    closed INTEGER NOT NULL,
+    opens_at INTEGER,
+    closes_at INTEGER,
+    ballots INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS admin_secrets (
+    poll_id TEXT PRIMARY KEY NOT NULL,
+    secret TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS voters (
+    user_id TEXT PRIMARY KEY NOT NULL,
+    token TEXT NOT NULL
+);
+";
+
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<SqliteStore, ApiError> {
+        let connection = Connection::open(path).storage("opening database")?;
+        connection
+            .execute_batch(SCHEMA)
+            .storage("creating tables")?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+/// inserts or replaces the row of the poll
+fn write_poll(connection: &Connection, poll: &PollV2) -> Result<(), ApiError> {
+    let summary = poll_summary(poll);
+    let json = serde_json::to_string(&Poll::V2(Box::new(poll.clone()))).storage("serializing")?;
+    connection
+        .execute(
+            "INSERT OR REPLACE INTO polls (id, poll, title, closed, opens_at, closes_at, ballots)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                summary.id.to_str(),
+                json,
+                summary.title,
+                summary.closed,
+                summary.opens_at.map(|t| t as i64),
+                summary.closes_at.map(|t| t as i64),
+                summary.ballots as i64,
+            ],
+        )
+        .storage("inserting into db")?;
+    Ok(())
+}
+
+fn read_poll(connection: &Connection, id: &PublicPollId) -> Result<Option<PollV2>, ApiError> {
+    let json: Option<String> = connection
+        .query_row(
+            "SELECT poll FROM polls WHERE id = ?1",
+            params![id.to_str()],
+            |row| row.get(0),
+        )
+        .optional()
+        .storage("loading")?;
+    match json {
+        Some(json) => {
+            let poll: Poll = serde_json::from_str(&json).storage("deserializing")?;
+            Ok(Some(poll.into_latest()))
+        }
+        None => Ok(None),
+    }
+}
+
+impl PollStore for SqliteStore {
+    fn get_poll(&self, id: &PublicPollId) -> Result<Option<PollV2>, ApiError> {
+        read_poll(&self.connection.lock().unwrap(), id)
+    }
+
+    fn insert_poll(&self, poll: &PollV2, secret: &AdminSecret) -> Result<(), ApiError> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction().storage("starting transaction")?;
+        tx.execute(
+            "INSERT OR REPLACE INTO admin_secrets (poll_id, secret) VALUES (?1, ?2)",
+            params![poll.id.to_str(), secret.to_str()],
+        )
+        .storage("inserting into db")?;
+        write_poll(&tx, poll)?;
+        tx.commit().storage("committing transaction")
+    }
+
+    fn update_poll(
+        &self,
+        id: &PublicPollId,
+        f: &dyn Fn(&mut PollV2) -> Result<(), ApiError>,
+    ) -> Result<PollV2, ApiError> {
+        let mut connection = self.connection.lock().unwrap();
+        // the connection is locked, so nothing else can change the poll in between
+        let tx = connection.transaction().storage("starting transaction")?;
+        let mut poll = read_poll(&tx, id)?.ok_or(ApiError::PollNotFound)?;
+        f(&mut poll)?;
+        write_poll(&tx, &poll)?;
+        tx.commit().storage("committing transaction")?;
+        Ok(poll)
+    }
+
+    fn delete_poll(&self, id: &PublicPollId) -> Result<(), ApiError> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction().storage("starting transaction")?;
+        tx.execute("DELETE FROM polls WHERE id = ?1", params![id.to_str()])
+            .storage("deleting from db")?;
+        tx.execute(
+            "DELETE FROM admin_secrets WHERE poll_id = ?1",
+            params![id.to_str()],
+        )
+        .storage("deleting from db")?;
+        tx.commit().storage("committing transaction")
+    }
+
+    fn admin_secret(&self, poll_id: &PublicPollId) -> Result<Option<AdminSecret>, ApiError> {
+        let secret: Option<String> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT secret FROM admin_secrets WHERE poll_id = ?1",
+                params![poll_id.to_str()],
+                |row| row.get(0),
+            )
+            .optional()
+            .storage("loading")?;
+        Ok(secret.map(AdminSecret::new))
+    }
+
+    fn visit_poll_summaries(
+        &self,
+        after: Option<&PublicPollId>,
+        visit: &mut dyn FnMut(PollSummary) -> bool,
+    ) -> Result<(), ApiError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT id, title, closed, opens_at, closes_at, ballots FROM polls
+                 WHERE ?1 IS NULL OR id > ?1 ORDER BY id",
+            )
+            .storage("loading")?;
+        let mut rows = statement
+            .query(params![after.map(|a| a.to_str())])
+            .storage("loading")?;
+        while let Some(row) = rows.next().storage("loading")? {
+            let summary = (|| -> rusqlite::Result<PollSummary> {
+                Ok(PollSummary {
+                    id: PublicPollId::from_str(row.get::<_, String>(0)?),
+                    title: row.get(1)?,
+                    closed: row.get(2)?,
+                    opens_at: row.get::<_, Option<i64>>(3)?.map(|t| t as u64),
+                    closes_at: row.get::<_, Option<i64>>(4)?.map(|t| t as u64),
+                    ballots: row.get::<_, i64>(5)? as usize,
+                })
+            })()
+            .storage("loading")?;
+            if !visit(summary) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_voter(&self, user_id: &PublicUserId, token: &VoterToken) -> Result<(), ApiError> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO voters (user_id, token) VALUES (?1, ?2)",
+                params![user_id.to_str(), token.to_str()],
+            )
+            .storage("inserting into db")?;
+        Ok(())
+    }
+
+    fn voter_token(&self, user_id: &PublicUserId) -> Result<Option<VoterToken>, ApiError> {
+        let token: Option<String> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT token FROM voters WHERE user_id = ?1",
+                params![user_id.to_str()],
+                |row| row.get(0),
+            )
+            .optional()
+            .storage("loading")?;
+        Ok(token.map(VoterToken::new))
+    }
+
+    fn schema_version(&self) -> Result<Option<u32>, ApiError> {
+        let version: u32 = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .storage("loading")?;
+        // SQLite starts every database at 0
+        Ok(Some(version).filter(|&v| v != 0))
+    }
+
+    fn set_schema_version(&self, version: u32) -> Result<(), ApiError> {
+        self.connection
+            .lock()
+            .unwrap()
+            .pragma_update(None, "user_version", version)
+            .storage("inserting into db")
+    }
+
+    fn poll_documents(&self) -> Result<Vec<PollDocument>, ApiError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT polls.id, polls.poll, admin_secrets.secret FROM polls
+                 LEFT JOIN admin_secrets ON admin_secrets.poll_id = polls.id ORDER BY polls.id",
+            )
+            .storage("loading")?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })
+            .storage("loading")?;
+        let mut documents = vec![];
+        for row in rows {
+            let (id, poll, admin_secret) = row.storage("loading")?;
+            documents.push(PollDocument {
+                id: PublicPollId::from_str(id),
+                poll: serde_json::from_str(&poll).storage("deserializing")?,
+                admin_secret: admin_secret.map(AdminSecret::new),
+            });
+        }
+        Ok(documents)
+    }
+}
@@ -0,0 +1,832 @@
+//! aggregation of score ballots into poll results
+use std::{cmp::Ordering, collections::HashMap};
+
+use common::{
+    BallotAggregates, CommitteeResult, CommitteeRound, MajorityGrade, OptionAggregate,
+    PairwiseResult, PollOption, PollOptionId, PollResult, PollV2, ProportionalMethod, ScoreVote,
+    StarFinalist, StarRunoff, TallyMethod,
+};
+
+pub fn compute_vote_result(poll: &PollV2) -> PollResult {
+    let method = poll.tally_method;
+    let options = &poll.options;
+    let votes = &poll.votes;
+    let mut map: HashMap<&PollOptionId, Vec<f64>> = HashMap::new();
+    for vote in votes {
+        for (id, r) in &vote.votes {
+            if let Some(r) = r {
+                map.entry(id).or_default().push(*r);
+            }
+        }
+    }
+    let scores: HashMap<PollOptionId, Option<f64>> = options
+        .iter()
+        .map(|o| {
+            let score = map
+                .get_mut(&o.id)
+                .filter(|v| !v.is_empty())
+                .map(|v| aggregate(method, v));
+            (o.id.clone(), score)
+        })
+        .collect();
+    let mut ranking = match method {
+        TallyMethod::MajorityJudgment => rank_by_majority_judgment(options, &mut map),
+        _ => rank_by_score(options, &scores),
+    };
+    let majority_judgment = match method {
+        TallyMethod::MajorityJudgment => Some(
+            options
+                .iter()
+                .filter_map(|o| {
+                    let grades = map.get(&o.id).filter(|g| !g.is_empty())?;
+                    Some((o.id.clone(), majority_grade(grades)))
+                })
+                .collect(),
+        ),
+        _ => None,
+    };
+    let runoff = match method {
+        TallyMethod::Star => star_runoff(&ranking, &scores, votes),
+        _ => None,
+    };
+    if let Some(runoff) = &runoff {
+        // the runoff decides the order of the two finalists, which are already the top two
+        if runoff.winner != ranking[0] {
+            ranking.swap(0, 1);
+        }
+    }
+    PollResult {
+        method,
+        scores,
+        ranking,
+        runoff,
+        majority_judgment,
+        committee: if poll.seats > 1 {
+            Some(elect_committee(poll))
+        } else {
+            None
+        },
+    }
+}
+
+/// runs the STAR runoff between the two highest-summed options of `ranking`.
+/// an abstention counts as lower than any score
+fn star_runoff(
+    ranking: &[PollOptionId],
+    sums: &HashMap<PollOptionId, Option<f64>>,
+    votes: &[ScoreVote],
+) -> Option<StarRunoff> {
+    let (a, b) = match ranking {
+        [a, b, ..] => (a, b),
+        _ => return None,
+    };
+    let sum_a = sums.get(a).copied().flatten()?;
+    let sum_b = sums.get(b).copied().flatten()?;
+    let mut prefer_a = 0;
+    let mut prefer_b = 0;
+    let mut no_preference = 0;
+    for vote in votes {
+        let score_a = vote.votes.get(a).copied().flatten();
+        let score_b = vote.votes.get(b).copied().flatten();
+        match score_a.partial_cmp(&score_b) {
+            Some(Ordering::Greater) => prefer_a += 1,
+            Some(Ordering::Less) => prefer_b += 1,
+            _ => no_preference += 1,
+        }
+    }
+    // a tied runoff goes to the option with the higher score sum, which is `a`
+    let winner = if prefer_b > prefer_a { b } else { a };
+    Some(StarRunoff {
+        finalists: vec![
+            StarFinalist {
+                option: a.clone(),
+                score_sum: sum_a,
+                preferred_by: prefer_a,
+            },
+            StarFinalist {
+                option: b.clone(),
+                score_sum: sum_b,
+                preferred_by: prefer_b,
+            },
+        ],
+        no_preference,
+        winner: winner.clone(),
+    })
+}
+
+/// aggregates a non-empty list of scores. the list may be reordered
+fn aggregate(method: TallyMethod, scores: &mut [f64]) -> f64 {
+    match method {
+        TallyMethod::Mean => mean(scores),
+        TallyMethod::Sum | TallyMethod::Star => scores.iter().sum(),
+        TallyMethod::Median => {
+            sort_scores(scores);
+            let mid = scores.len() / 2;
+            if scores.len() % 2 == 1 {
+                scores[mid]
+            } else {
+                (scores[mid - 1] + scores[mid]) / 2.0
+            }
+        }
+        TallyMethod::TrimmedMean { trim } => {
+            sort_scores(scores);
+            let cut = (scores.len() as f64 * trim.clamp(0.0, 0.5)).floor() as usize;
+            if cut * 2 >= scores.len() {
+                // everything would be trimmed away, keep the middle
+                let mid = scores.len() / 2;
+                return mean(&scores[mid - (1 - scores.len() % 2)..=mid]);
+            }
+            mean(&scores[cut..scores.len() - cut])
+        }
+        TallyMethod::MajorityJudgment => {
+            sort_scores(scores);
+            lower_median(scores)
+        }
+    }
+}
+
+/// the lower median of a sorted, non-empty list
+fn lower_median(sorted: &[f64]) -> f64 {
+    sorted[(sorted.len() - 1) / 2]
+}
+
+/// computes the median grade and majority gauge of a sorted, non-empty list of grades
+fn majority_grade(sorted: &[f64]) -> MajorityGrade {
+    let median = lower_median(sorted);
+    let n = sorted.len() as f64;
+    MajorityGrade {
+        median,
+        above: sorted.iter().filter(|g| **g > median).count() as f64 / n,
+        below: sorted.iter().filter(|g| **g < median).count() as f64 / n,
+    }
+}
+
+/// compares two sorted lists of grades by majority judgment: as long as the medians are equal,
+/// one median grade is removed from both lists. options without grades lose
+fn compare_majority_judgment(a: &[f64], b: &[f64]) -> Ordering {
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    loop {
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (false, true) => return Ordering::Greater,
+            (true, false) => return Ordering::Less,
+            (false, false) => {}
+        }
+        let median_a = lower_median(&a);
+        let median_b = lower_median(&b);
+        match median_a.partial_cmp(&median_b) {
+            Some(Ordering::Equal) | None => {
+                a.remove((a.len() - 1) / 2);
+                b.remove((b.len() - 1) / 2);
+            }
+            Some(o) => return o,
+        }
+    }
+}
+
+/// orders the options by majority judgment, winner first.
+/// complete ties keep the order in which the options were defined
+fn rank_by_majority_judgment(
+    options: &[PollOption],
+    grades: &mut HashMap<&PollOptionId, Vec<f64>>,
+) -> Vec<PollOptionId> {
+    for g in grades.values_mut() {
+        sort_scores(g);
+    }
+    let mut ranking: Vec<PollOptionId> = options.iter().map(|o| o.id.clone()).collect();
+    ranking.sort_by(|a, b| {
+        let a = grades.get(a).map(|g| g.as_slice()).unwrap_or(&[]);
+        let b = grades.get(b).map(|g| g.as_slice()).unwrap_or(&[]);
+        compare_majority_judgment(b, a)
+    });
+    ranking
+}
+
+fn mean(scores: &[f64]) -> f64 {
+    scores.iter().sum::<f64>() / (scores.len() as f64)
+}
+
+fn sort_scores(scores: &mut [f64]) {
+    scores.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+}
+
+/// orders the options by descending score, options without a score last.
+/// ties keep the order in which the options were defined
+pub fn rank_by_score(
+    options: &[PollOption],
+    scores: &HashMap<PollOptionId, Option<f64>>,
+) -> Vec<PollOptionId> {
+    let mut ranking: Vec<PollOptionId> = options.iter().map(|o| o.id.clone()).collect();
+    ranking.sort_by(|a, b| {
+        let a = scores.get(a).copied().flatten();
+        let b = scores.get(b).copied().flatten();
+        match (a, b) {
+            (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    });
+    ranking
+}
+
+/// elects `poll.seats` options one by one. in every round the option with the highest weighted
+/// score sum is elected, then the ballot weights are reduced according to the proportional method.
+/// scores are normalized to [0, 1] on the poll's scale, abstentions count as 0
+fn elect_committee(poll: &PollV2) -> CommitteeResult {
+    let normalized = |vote: &ScoreVote, id: &PollOptionId| -> f64 {
+        vote.votes
+            .get(id)
+            .copied()
+            .flatten()
+            .map(|s| poll.score_scale.normalize(s))
+            .unwrap_or(0.0)
+    };
+    let quota = poll.votes.len() as f64 / poll.seats as f64;
+    let mut weights = vec![1.0f64; poll.votes.len()];
+    let mut remaining: Vec<&PollOptionId> = poll.options.iter().map(|o| &o.id).collect();
+    let mut winners: Vec<PollOptionId> = vec![];
+    let mut rounds = vec![];
+    while winners.len() < poll.seats as usize && !remaining.is_empty() {
+        if poll.proportional_method == ProportionalMethod::ReweightedRange {
+            for (weight, vote) in weights.iter_mut().zip(&poll.votes) {
+                let support: f64 = winners.iter().map(|w| normalized(vote, w)).sum();
+                *weight = 1.0 / (1.0 + support);
+            }
+        }
+        let weighted_scores: HashMap<PollOptionId, f64> = remaining
+            .iter()
+            .map(|id| {
+                let sum = weights
+                    .iter()
+                    .zip(&poll.votes)
+                    .map(|(w, vote)| w * normalized(vote, id))
+                    .sum();
+                ((*id).clone(), sum)
+            })
+            .collect();
+        // ties go to the option that was defined first
+        let index = (1..remaining.len()).fold(0, |best, i| {
+            if weighted_scores[remaining[i]] > weighted_scores[remaining[best]] {
+                i
+            } else {
+                best
+            }
+        });
+        let elected = remaining.remove(index).clone();
+        rounds.push(CommitteeRound {
+            ballot_weights: weights.clone(),
+            weighted_scores: weighted_scores.clone(),
+            elected: elected.clone(),
+        });
+        if poll.proportional_method == ProportionalMethod::SequentiallySpentScore {
+            let total = weighted_scores[&elected];
+            if total > 0.0 {
+                let spent_fraction = (quota / total).min(1.0);
+                for (weight, vote) in weights.iter_mut().zip(&poll.votes) {
+                    *weight *= 1.0 - normalized(vote, &elected) * spent_fraction;
+                }
+            }
+        }
+        winners.push(elected);
+    }
+    CommitteeResult {
+        method: poll.proportional_method,
+        winners,
+        rounds,
+    }
+}
+
+/// builds the pairwise preference matrix of all options and finds the Condorcet winner,
+/// falling back to the Schulze ordering if there is none
+pub fn pairwise(options: &[PollOption], votes: &[ScoreVote]) -> PairwiseResult {
+    let n = options.len();
+    let mut matrix = vec![vec![0usize; n]; n];
+    for vote in votes {
+        let scores: Vec<Option<f64>> = options
+            .iter()
+            .map(|o| vote.votes.get(&o.id).copied().flatten())
+            .collect();
+        for i in 0..n {
+            for j in 0..n {
+                if scores[i].partial_cmp(&scores[j]) == Some(Ordering::Greater) {
+                    matrix[i][j] += 1;
+                }
+            }
+        }
+    }
+    let condorcet_winner = (0..n).find(|&i| (0..n).all(|j| i == j || matrix[i][j] > matrix[j][i]));
+    let schulze_ranking = match condorcet_winner {
+        Some(_) => None,
+        None => Some(
+            schulze_ranking(&matrix)
+                .into_iter()
+                .map(|group| group.into_iter().map(|i| options[i].id.clone()).collect())
+                .collect(),
+        ),
+    };
+    PairwiseResult {
+        options: options.iter().map(|o| o.id.clone()).collect(),
+        matrix,
+        condorcet_winner: condorcet_winner.map(|i| options[i].id.clone()),
+        schulze_ranking,
+    }
+}
+
+/// orders the option indices of a pairwise matrix by the Schulze method, winner group first
+fn schulze_ranking(matrix: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = matrix.len();
+    // strength of the strongest path from i to j
+    let mut strength = vec![vec![0usize; n]; n];
+    for i in 0..n {
+        for j in 0..n {
+            if i != j && matrix[i][j] > matrix[j][i] {
+                strength[i][j] = matrix[i][j];
+            }
+        }
+    }
+    for k in 0..n {
+        for i in 0..n {
+            for j in 0..n {
+                if i != j && i != k && j != k {
+                    strength[i][j] = strength[i][j].max(strength[i][k].min(strength[k][j]));
+                }
+            }
+        }
+    }
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut ranking = vec![];
+    while !remaining.is_empty() {
+        // the options that are not beaten by any other remaining option
+        let (group, rest): (Vec<usize>, Vec<usize>) = remaining
+            .iter()
+            .partition(|&&i| remaining.iter().all(|&j| strength[j][i] <= strength[i][j]));
+        if group.is_empty() {
+            // can't happen since the schulze relation is transitive, but don't loop forever
+            ranking.push(rest);
+            break;
+        }
+        ranking.push(group);
+        remaining = rest;
+    }
+    ranking
+}
+
+/// per-option score distribution, count and mean, without anything that identifies a ballot
+pub fn ballot_aggregates(poll: &PollV2) -> BallotAggregates {
+    let scale = &poll.score_scale;
+    let values = scale.values();
+    let options = poll
+        .options
+        .iter()
+        .map(|o| {
+            let mut aggregate = OptionAggregate {
+                distribution: vec![0; values.len()],
+                ..Default::default()
+            };
+            let mut sum = 0.0;
+            for vote in &poll.votes {
+                match vote.votes.get(&o.id).copied().flatten() {
+                    Some(score) => {
+                        let index = ((score - scale.min) / scale.step).round() as usize;
+                        aggregate.distribution[index.min(values.len() - 1)] += 1;
+                        aggregate.count += 1;
+                        sum += score;
+                    }
+                    None => aggregate.abstentions += 1,
+                }
+            }
+            if aggregate.count > 0 {
+                aggregate.mean = Some(sum / aggregate.count as f64);
+            }
+            (o.id.clone(), aggregate)
+        })
+        .collect();
+    BallotAggregates {
+        ballots: poll.votes.len(),
+        options,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{PublicPollId, PublicUserId, ResultVisibility, ScoreScale};
+
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<PollOptionId> {
+        ids.iter()
+            .map(|id| PollOptionId::from_str(id.to_string()))
+            .collect()
+    }
+
+    /// a poll on the default 0..9 scale with one ballot per row, the scores in the order of
+    /// `options`
+    fn poll(options: &[&str], rows: &[&[Option<f64>]], method: TallyMethod) -> PollV2 {
+        let option_ids = ids(options);
+        PollV2 {
+            id: PublicPollId::from_str("test"),
+            title: "test".to_string(),
+            description_text_markdown: String::new(),
+            options: option_ids
+                .iter()
+                .map(|id| PollOption {
+                    id: id.clone(),
+                    title: id.to_str().to_string(),
+                    description_text_markdown: String::new(),
+                })
+                .collect(),
+            score_scale: ScoreScale::default(),
+            votes: rows
+                .iter()
+                .enumerate()
+                .map(|(i, row)| ScoreVote {
+                    user_id: PublicUserId::from_str(format!("voter{i}")),
+                    user_name: format!("voter {i}"),
+                    votes: option_ids
+                        .iter()
+                        .cloned()
+                        .zip(row.iter().copied())
+                        .collect(),
+                })
+                .collect(),
+            tally_method: method,
+            seats: 1,
+            proportional_method: ProportionalMethod::default(),
+            result: None,
+            vote_history: vec![],
+            closed: false,
+            opens_at: None,
+            closes_at: None,
+            result_visibility: ResultVisibility::default(),
+            anonymous: false,
+        }
+    }
+
+    fn score(result: &PollResult, id: &str) -> Option<f64> {
+        result.scores[&PollOptionId::from_str(id.to_string())]
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn mean_sum_and_median() {
+        let rows: &[&[Option<f64>]] = &[
+            &[Some(1.0), Some(3.0)],
+            &[Some(2.0), Some(3.0)],
+            &[Some(6.0), Some(3.0)],
+        ];
+        let mean = compute_vote_result(&poll(&["a", "b"], rows, TallyMethod::Mean));
+        assert_close(score(&mean, "a").unwrap(), 3.0);
+        assert_close(score(&mean, "b").unwrap(), 3.0);
+        // a tie keeps the order in which the options were defined
+        assert_eq!(mean.ranking, ids(&["a", "b"]));
+
+        let sum = compute_vote_result(&poll(&["a", "b"], rows, TallyMethod::Sum));
+        assert_close(score(&sum, "a").unwrap(), 9.0);
+        assert_close(score(&sum, "b").unwrap(), 9.0);
+
+        let median = compute_vote_result(&poll(&["a", "b"], rows, TallyMethod::Median));
+        assert_close(score(&median, "a").unwrap(), 2.0);
+        assert_close(score(&median, "b").unwrap(), 3.0);
+        assert_eq!(median.ranking, ids(&["b", "a"]));
+    }
+
+    #[test]
+    fn median_of_an_even_number_of_scores() {
+        let rows: &[&[Option<f64>]] = &[&[Some(1.0)], &[Some(2.0)], &[Some(4.0)], &[Some(9.0)]];
+        let result = compute_vote_result(&poll(&["a"], rows, TallyMethod::Median));
+        assert_close(score(&result, "a").unwrap(), 3.0);
+    }
+
+    #[test]
+    fn abstentions_are_not_scores() {
+        let rows: &[&[Option<f64>]] = &[&[Some(4.0), None, None], &[None, Some(2.0), None]];
+        let result = compute_vote_result(&poll(&["a", "b", "c"], rows, TallyMethod::Mean));
+        assert_close(score(&result, "a").unwrap(), 4.0);
+        assert_close(score(&result, "b").unwrap(), 2.0);
+        assert_eq!(score(&result, "c"), None);
+        // options nobody scored come last
+        assert_eq!(result.ranking, ids(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn trimmed_mean_drops_the_extremes() {
+        let rows: &[&[Option<f64>]] = &[
+            &[Some(0.0)],
+            &[Some(5.0)],
+            &[Some(5.0)],
+            &[Some(5.0)],
+            &[Some(9.0)],
+        ];
+        let mean = compute_vote_result(&poll(&["a"], rows, TallyMethod::Mean));
+        assert_close(score(&mean, "a").unwrap(), 4.8);
+        // 20% of 5 scores is one score on each side
+        let trimmed =
+            compute_vote_result(&poll(&["a"], rows, TallyMethod::TrimmedMean { trim: 0.2 }));
+        assert_close(score(&trimmed, "a").unwrap(), 5.0);
+        // less than one score on each side trims nothing
+        let untrimmed =
+            compute_vote_result(&poll(&["a"], rows, TallyMethod::TrimmedMean { trim: 0.1 }));
+        assert_close(score(&untrimmed, "a").unwrap(), 4.8);
+    }
+
+    #[test]
+    fn trimmed_mean_keeps_the_middle_when_everything_would_be_trimmed() {
+        let trim = TallyMethod::TrimmedMean { trim: 0.5 };
+        assert_close(aggregate(trim, &mut [3.0, 1.0]), 2.0);
+        assert_close(aggregate(trim, &mut [9.0, 1.0, 4.0]), 4.0);
+        assert_close(aggregate(trim, &mut [7.0]), 7.0);
+    }
+
+    #[test]
+    fn no_ballots() {
+        for method in [
+            TallyMethod::Mean,
+            TallyMethod::Sum,
+            TallyMethod::Median,
+            TallyMethod::TrimmedMean { trim: 0.2 },
+        ] {
+            let result = compute_vote_result(&poll(&["a", "b"], &[], method));
+            assert_eq!(score(&result, "a"), None);
+            assert_eq!(score(&result, "b"), None);
+            assert_eq!(result.ranking, ids(&["a", "b"]));
+            assert!(result.runoff.is_none());
+            assert!(result.committee.is_none());
+        }
+    }
+
+    #[test]
+    fn star_runoff_can_overturn_the_scoring_round() {
+        let rows: &[&[Option<f64>]] = &[
+            &[Some(5.0), Some(0.0)],
+            &[Some(5.0), Some(0.0)],
+            &[Some(0.0), Some(1.0)],
+            &[Some(0.0), Some(1.0)],
+            &[Some(0.0), Some(1.0)],
+        ];
+        let result = compute_vote_result(&poll(&["a", "b"], rows, TallyMethod::Star));
+        assert_close(score(&result, "a").unwrap(), 10.0);
+        assert_close(score(&result, "b").unwrap(), 3.0);
+        let runoff = result.runoff.unwrap();
+        assert_eq!(runoff.finalists[0].option, ids(&["a"])[0]);
+        assert_eq!(runoff.finalists[0].preferred_by, 2);
+        assert_eq!(runoff.finalists[1].preferred_by, 3);
+        assert_eq!(runoff.no_preference, 0);
+        assert_eq!(runoff.winner, ids(&["b"])[0]);
+        assert_eq!(result.ranking, ids(&["b", "a"]));
+    }
+
+    #[test]
+    fn star_runoff_tie_goes_to_the_higher_score_sum() {
+        let rows: &[&[Option<f64>]] = &[
+            &[Some(5.0), Some(0.0), Some(0.0)],
+            &[Some(0.0), Some(1.0), Some(0.0)],
+            &[Some(2.0), Some(2.0), Some(0.0)],
+        ];
+        let result = compute_vote_result(&poll(&["a", "b", "c"], rows, TallyMethod::Star));
+        let runoff = result.runoff.unwrap();
+        assert_eq!(runoff.finalists[0].preferred_by, 1);
+        assert_eq!(runoff.finalists[1].preferred_by, 1);
+        assert_eq!(runoff.no_preference, 1);
+        assert_eq!(runoff.winner, ids(&["a"])[0]);
+        assert_eq!(result.ranking, ids(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn star_runoff_counts_an_abstention_as_lowest() {
+        let rows: &[&[Option<f64>]] = &[&[Some(0.0), None], &[Some(0.0), None], &[None, Some(9.0)]];
+        let result = compute_vote_result(&poll(&["a", "b"], rows, TallyMethod::Star));
+        let runoff = result.runoff.unwrap();
+        assert_eq!(runoff.finalists[0].option, ids(&["b"])[0]);
+        assert_eq!(runoff.finalists[0].preferred_by, 1);
+        assert_eq!(runoff.finalists[1].preferred_by, 2);
+        assert_eq!(runoff.winner, ids(&["a"])[0]);
+        assert_eq!(result.ranking, ids(&["a", "b"]));
+    }
+
+    #[test]
+    fn star_needs_two_scored_options() {
+        let one_option = compute_vote_result(&poll(&["a"], &[&[Some(3.0)]], TallyMethod::Star));
+        assert!(one_option.runoff.is_none());
+        let one_scored =
+            compute_vote_result(&poll(&["a", "b"], &[&[Some(3.0), None]], TallyMethod::Star));
+        assert!(one_scored.runoff.is_none());
+        assert_eq!(one_scored.ranking, ids(&["a", "b"]));
+        let no_ballots = compute_vote_result(&poll(&["a", "b"], &[], TallyMethod::Star));
+        assert!(no_ballots.runoff.is_none());
+    }
+
+    #[test]
+    fn majority_judgment_ranks_by_median_grade() {
+        let rows: &[&[Option<f64>]] = &[
+            &[Some(0.0), Some(3.0)],
+            &[Some(4.0), Some(3.0)],
+            &[Some(4.0), Some(3.0)],
+        ];
+        let result = compute_vote_result(&poll(&["a", "b"], rows, TallyMethod::MajorityJudgment));
+        assert_close(score(&result, "a").unwrap(), 4.0);
+        assert_close(score(&result, "b").unwrap(), 3.0);
+        assert_eq!(result.ranking, ids(&["a", "b"]));
+    }
+
+    #[test]
+    fn majority_judgment_breaks_ties_by_removing_median_grades() {
+        // both medians are 2. without one median grade, a has [1, 3] with the lower median 1
+        // and b has [2, 2]
+        let rows: &[&[Option<f64>]] = &[
+            &[Some(1.0), Some(2.0)],
+            &[Some(2.0), Some(2.0)],
+            &[Some(3.0), Some(2.0)],
+        ];
+        let result = compute_vote_result(&poll(&["a", "b"], rows, TallyMethod::MajorityJudgment));
+        assert_close(score(&result, "a").unwrap(), 2.0);
+        assert_close(score(&result, "b").unwrap(), 2.0);
+        assert_eq!(result.ranking, ids(&["b", "a"]));
+    }
+
+    #[test]
+    fn majority_judgment_complete_ties_and_ungraded_options() {
+        let rows: &[&[Option<f64>]] =
+            &[&[None, Some(1.0), Some(1.0)], &[None, Some(5.0), Some(5.0)]];
+        let result =
+            compute_vote_result(&poll(&["a", "b", "c"], rows, TallyMethod::MajorityJudgment));
+        assert_eq!(result.ranking, ids(&["b", "c", "a"]));
+        let grades = result.majority_judgment.unwrap();
+        assert!(!grades.contains_key(&ids(&["a"])[0]));
+        assert_eq!(
+            compare_majority_judgment(&[1.0, 5.0], &[1.0, 5.0]),
+            Ordering::Equal
+        );
+        assert_eq!(compare_majority_judgment(&[0.0], &[]), Ordering::Greater);
+    }
+
+    #[test]
+    fn majority_gauge() {
+        let grade = majority_grade(&[1.0, 2.0, 3.0, 3.0]);
+        assert_close(grade.median, 2.0);
+        assert_close(grade.above, 0.5);
+        assert_close(grade.below, 0.25);
+        assert_close(grade.gauge(), 0.5);
+        let grade = majority_grade(&[0.0, 0.0, 4.0]);
+        assert_close(grade.median, 0.0);
+        assert_close(grade.gauge(), 1.0 / 3.0);
+        let grade = majority_grade(&[0.0, 4.0, 4.0]);
+        assert_close(grade.gauge(), -1.0 / 3.0);
+    }
+
+    #[test]
+    fn majority_judgment_without_ballots() {
+        let result = compute_vote_result(&poll(&["a", "b"], &[], TallyMethod::MajorityJudgment));
+        assert_eq!(result.ranking, ids(&["a", "b"]));
+        assert!(result.majority_judgment.unwrap().is_empty());
+    }
+
+    fn pairwise_of(options: &[&str], rows: &[&[Option<f64>]]) -> PairwiseResult {
+        let poll = poll(options, rows, TallyMethod::Mean);
+        pairwise(&poll.options, &poll.votes)
+    }
+
+    #[test]
+    fn pairwise_condorcet_winner() {
+        let rows: &[&[Option<f64>]] = &[
+            &[Some(3.0), Some(2.0), Some(1.0)],
+            &[Some(3.0), Some(2.0), Some(1.0)],
+            &[Some(1.0), Some(3.0), Some(2.0)],
+        ];
+        let result = pairwise_of(&["a", "b", "c"], rows);
+        assert_eq!(
+            result.matrix,
+            vec![vec![0, 2, 2], vec![1, 0, 3], vec![1, 0, 0]]
+        );
+        assert_eq!(result.condorcet_winner, Some(ids(&["a"])[0].clone()));
+        assert!(result.schulze_ranking.is_none());
+    }
+
+    #[test]
+    fn pairwise_counts_an_abstention_as_lowest() {
+        let result = pairwise_of(&["a", "b", "c"], &[&[Some(0.0), None, None]]);
+        assert_eq!(
+            result.matrix,
+            vec![vec![0, 1, 1], vec![0, 0, 0], vec![0, 0, 0]]
+        );
+        assert_eq!(result.condorcet_winner, Some(ids(&["a"])[0].clone()));
+    }
+
+    #[test]
+    fn schulze_breaks_a_cycle() {
+        // a beats b 5:2, b beats c 5:2, c beats a 4:3. the weakest defeat is c over a
+        let mut rows: Vec<&[Option<f64>]> = vec![];
+        rows.extend([&[Some(3.0), Some(2.0), Some(1.0)] as &[_]; 3]);
+        rows.extend([&[Some(1.0), Some(3.0), Some(2.0)] as &[_]; 2]);
+        rows.extend([&[Some(2.0), Some(1.0), Some(3.0)] as &[_]; 2]);
+        let result = pairwise_of(&["a", "b", "c"], &rows);
+        assert_eq!(
+            result.matrix,
+            vec![vec![0, 5, 3], vec![2, 0, 5], vec![4, 2, 0]]
+        );
+        assert_eq!(result.condorcet_winner, None);
+        assert_eq!(
+            result.schulze_ranking,
+            Some(vec![ids(&["a"]), ids(&["b"]), ids(&["c"])])
+        );
+    }
+
+    #[test]
+    fn schulze_ties_a_symmetric_cycle() {
+        let rows: &[&[Option<f64>]] = &[
+            &[Some(3.0), Some(2.0), Some(1.0)],
+            &[Some(1.0), Some(3.0), Some(2.0)],
+            &[Some(2.0), Some(1.0), Some(3.0)],
+        ];
+        let result = pairwise_of(&["a", "b", "c"], rows);
+        assert_eq!(result.condorcet_winner, None);
+        assert_eq!(result.schulze_ranking, Some(vec![ids(&["a", "b", "c"])]));
+    }
+
+    #[test]
+    fn pairwise_without_ballots() {
+        let result = pairwise_of(&["a", "b"], &[]);
+        assert_eq!(result.matrix, vec![vec![0, 0], vec![0, 0]]);
+        assert_eq!(result.condorcet_winner, None);
+        assert_eq!(result.schulze_ranking, Some(vec![ids(&["a", "b"])]));
+    }
+
+    /// six ballots that like a and b, four that only like c
+    fn two_factions(seats: u32, method: ProportionalMethod) -> PollV2 {
+        let mut rows: Vec<&[Option<f64>]> = vec![];
+        rows.extend([&[Some(9.0), Some(9.0), Some(0.0)] as &[_]; 6]);
+        rows.extend([&[Some(0.0), Some(0.0), Some(9.0)] as &[_]; 4]);
+        let mut poll = poll(&["a", "b", "c"], &rows, TallyMethod::Mean);
+        poll.seats = seats;
+        poll.proportional_method = method;
+        poll
+    }
+
+    #[test]
+    fn reweighted_range_voting_elects_the_minority() {
+        let committee = elect_committee(&two_factions(2, ProportionalMethod::ReweightedRange));
+        // a and b tie at 6 in the first round, a was defined first
+        assert_close(committee.rounds[0].weighted_scores[&ids(&["a"])[0]], 6.0);
+        assert_close(committee.rounds[0].weighted_scores[&ids(&["b"])[0]], 6.0);
+        // the first faction's ballots count half once a is elected: b has 3, c has 4
+        assert_close(committee.rounds[1].ballot_weights[0], 0.5);
+        assert_close(committee.rounds[1].ballot_weights[9], 1.0);
+        assert_close(committee.rounds[1].weighted_scores[&ids(&["b"])[0]], 3.0);
+        assert_close(committee.rounds[1].weighted_scores[&ids(&["c"])[0]], 4.0);
+        assert_eq!(committee.winners, ids(&["a", "c"]));
+    }
+
+    #[test]
+    fn sequentially_spent_score_elects_the_minority() {
+        let committee =
+            elect_committee(&two_factions(2, ProportionalMethod::SequentiallySpentScore));
+        // electing a spends the quota of 10 / 2 = 5 from the six ballots that gave it 6 in total
+        assert_close(committee.rounds[1].ballot_weights[0], 1.0 / 6.0);
+        assert_close(committee.rounds[1].ballot_weights[9], 1.0);
+        assert_close(committee.rounds[1].weighted_scores[&ids(&["b"])[0]], 1.0);
+        assert_close(committee.rounds[1].weighted_scores[&ids(&["c"])[0]], 4.0);
+        assert_eq!(committee.winners, ids(&["a", "c"]));
+    }
+
+    #[test]
+    fn committee_with_as_many_seats_as_options() {
+        for method in [
+            ProportionalMethod::ReweightedRange,
+            ProportionalMethod::SequentiallySpentScore,
+        ] {
+            let result = compute_vote_result(&two_factions(3, method));
+            let committee = result.committee.unwrap();
+            assert_eq!(committee.winners, ids(&["a", "c", "b"]));
+            assert_eq!(committee.rounds.len(), 3);
+        }
+    }
+
+    #[test]
+    fn committee_counts_abstentions_as_zero() {
+        let mut poll = poll(&["a", "b"], &[&[None, Some(9.0)]], TallyMethod::Mean);
+        poll.seats = 2;
+        assert_eq!(elect_committee(&poll).winners, ids(&["b", "a"]));
+    }
+
+    #[test]
+    fn committee_without_ballots() {
+        for method in [
+            ProportionalMethod::ReweightedRange,
+            ProportionalMethod::SequentiallySpentScore,
+        ] {
+            let mut poll = poll(&["a", "b", "c"], &[], TallyMethod::Mean);
+            poll.seats = 2;
+            poll.proportional_method = method;
+            let committee = elect_committee(&poll);
+            assert_eq!(committee.winners, ids(&["a", "b"]));
+            assert!(committee.rounds[0].ballot_weights.is_empty());
+        }
+    }
+}
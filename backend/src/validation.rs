@@ -0,0 +1,227 @@
+//! checks that ballots are well-formed before they are stored
+use common::{ApiError, BallotError, PollV2, ScoreVote};
+
+/// rejects ballots without a user name or scores, and scores that don't fit the poll.
+/// anonymous polls don't need a user name since it is never shown
+pub fn validate_ballot(poll: &PollV2, vote: &ScoreVote) -> Result<(), BallotError> {
+    if !poll.anonymous && vote.user_name.trim().is_empty() {
+        return Err(BallotError::EmptyUserName);
+    }
+    if vote.votes.values().all(|score| score.is_none()) {
+        return Err(BallotError::EmptyBallot);
+    }
+    let scale = &poll.score_scale;
+    for (option, score) in &vote.votes {
+        if !poll.options.iter().any(|o| &o.id == option) {
+            return Err(BallotError::UnknownOption {
+                option: option.clone(),
+            });
+        }
+        if let Some(score) = *score {
+            if !score.is_finite() {
+                return Err(BallotError::NonFiniteScore {
+                    option: option.clone(),
+                });
+            }
+            if !scale.is_in_range(score) {
+                return Err(BallotError::ScoreOutOfScale {
+                    option: option.clone(),
+                    score,
+                });
+            }
+            if !scale.is_on_step(score) {
+                return Err(BallotError::ScoreNotOnStep {
+                    option: option.clone(),
+                    score,
+                });
+            }
+        }
+    }
+    if !scale.allow_abstain {
+        if let Some(o) = poll
+            .options
+            .iter()
+            .find(|o| vote.votes.get(&o.id).copied().flatten().is_none())
+        {
+            return Err(BallotError::MissingScore {
+                option: o.id.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// makes sure the poll accepts ballots at the unix timestamp `now`
+pub fn check_open(poll: &PollV2, now: u64) -> Result<(), ApiError> {
+    if poll.closed {
+        return Err(ApiError::PollClosed);
+    }
+    let too_early = poll.opens_at.is_some_and(|t| now < t);
+    let too_late = poll.closes_at.is_some_and(|t| now >= t);
+    if too_early || too_late {
+        return Err(ApiError::OutsideVotingPeriod {
+            opens_at: poll.opens_at,
+            closes_at: poll.closes_at,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{
+        PollOption, PollOptionId, ProportionalMethod, PublicPollId, PublicUserId, ResultVisibility,
+        ScoreScale, TallyMethod,
+    };
+
+    use super::*;
+
+    fn id(id: &str) -> PollOptionId {
+        PollOptionId::from_str(id.to_string())
+    }
+
+    /// a poll with the options "a" and "b" on a 0..10 scale in steps of 0.5
+    fn poll() -> PollV2 {
+        PollV2 {
+            id: PublicPollId::from_str("test"),
+            title: "test".to_string(),
+            description_text_markdown: String::new(),
+            options: ["a", "b"]
+                .into_iter()
+                .map(|o| PollOption {
+                    id: id(o),
+                    title: o.to_string(),
+                    description_text_markdown: String::new(),
+                })
+                .collect(),
+            score_scale: ScoreScale {
+                min: 0.0,
+                max: 10.0,
+                step: 0.5,
+                allow_abstain: true,
+            },
+            votes: vec![],
+            tally_method: TallyMethod::default(),
+            seats: 1,
+            proportional_method: ProportionalMethod::default(),
+            result: None,
+            vote_history: vec![],
+            closed: false,
+            opens_at: None,
+            closes_at: None,
+            result_visibility: ResultVisibility::default(),
+            anonymous: false,
+        }
+    }
+
+    fn ballot(scores: &[(&str, Option<f64>)]) -> ScoreVote {
+        ScoreVote {
+            user_id: PublicUserId::from_str("voter"),
+            user_name: "voter".to_string(),
+            votes: scores.iter().map(|&(o, score)| (id(o), score)).collect(),
+        }
+    }
+
+    #[test]
+    fn accepts_scores_on_the_scale() {
+        let vote = ballot(&[("a", Some(0.0)), ("b", Some(9.5))]);
+        assert_eq!(validate_ballot(&poll(), &vote), Ok(()));
+        let vote = ballot(&[("a", Some(10.0)), ("b", None)]);
+        assert_eq!(validate_ballot(&poll(), &vote), Ok(()));
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        let vote = ballot(&[("a", Some(1.0)), ("c", Some(1.0))]);
+        assert_eq!(
+            validate_ballot(&poll(), &vote),
+            Err(BallotError::UnknownOption { option: id("c") })
+        );
+    }
+
+    #[test]
+    fn rejects_scores_out_of_range() {
+        for score in [-0.5, 10.5, 100.0] {
+            let vote = ballot(&[("a", Some(score))]);
+            assert_eq!(
+                validate_ballot(&poll(), &vote),
+                Err(BallotError::ScoreOutOfScale {
+                    option: id("a"),
+                    score
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_scores_off_step() {
+        for score in [0.25, 3.3, 9.99] {
+            let vote = ballot(&[("a", Some(score))]);
+            assert_eq!(
+                validate_ballot(&poll(), &vote),
+                Err(BallotError::ScoreNotOnStep {
+                    option: id("a"),
+                    score
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn duplicate_options_keep_the_last_score_which_is_validated() {
+        let parse = |json: &str| -> ScoreVote { serde_json::from_str(json).unwrap() };
+        let vote =
+            parse(r#"{"user_id": "voter", "user_name": "voter", "votes": {"a": 12, "a": 3}}"#);
+        assert_eq!(vote.votes.len(), 1);
+        assert_eq!(validate_ballot(&poll(), &vote), Ok(()));
+        let vote =
+            parse(r#"{"user_id": "voter", "user_name": "voter", "votes": {"a": 3, "a": 12}}"#);
+        assert_eq!(
+            validate_ballot(&poll(), &vote),
+            Err(BallotError::ScoreOutOfScale {
+                option: id("a"),
+                score: 12.0
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_empty_ballots() {
+        assert_eq!(
+            validate_ballot(&poll(), &ballot(&[])),
+            Err(BallotError::EmptyBallot)
+        );
+        let vote = ballot(&[("a", None), ("b", None)]);
+        assert_eq!(
+            validate_ballot(&poll(), &vote),
+            Err(BallotError::EmptyBallot)
+        );
+    }
+
+    #[test]
+    fn polls_are_open_between_opens_at_and_closes_at() {
+        let timed = PollV2 {
+            opens_at: Some(100),
+            closes_at: Some(200),
+            ..poll()
+        };
+        let outside = Err(ApiError::OutsideVotingPeriod {
+            opens_at: Some(100),
+            closes_at: Some(200),
+        });
+        assert_eq!(check_open(&timed, 99), outside);
+        assert_eq!(check_open(&timed, 100), Ok(()));
+        assert_eq!(check_open(&timed, 199), Ok(()));
+        assert_eq!(check_open(&timed, 200), outside);
+        assert_eq!(check_open(&poll(), 0), Ok(()));
+    }
+
+    #[test]
+    fn closed_polls_are_not_open() {
+        let poll = PollV2 {
+            closed: true,
+            ..poll()
+        };
+        assert_eq!(check_open(&poll, 0), Err(ApiError::PollClosed));
+    }
+}
@@ -0,0 +1,288 @@
+//! `svt`: a command line client of the score voting server, for scripting polls
+mod poll_file;
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use common::{
+    ApiClient, ClientError, CreatePoll, PollOptionId, PollV2, PollView, PublicPollId, ScoreVote,
+    VoterIdentity,
+};
+use poll_file::PollFile;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+struct Args {
+    /// url of the HTTP endpoint of the server
+    #[structopt(long, env = "SVT_SERVER", default_value = "http://localhost:3030/")]
+    server: String,
+    /// print the server's responses as JSON instead of text
+    #[structopt(long)]
+    json: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// creates a poll and prints its id and admin secret
+    CreatePoll {
+        /// TOML file describing the poll
+        #[structopt(long)]
+        from: PathBuf,
+    },
+    /// casts or replaces the ballot of this client's voter identity
+    Vote {
+        poll_id: String,
+        /// scores as <option>=<score>, the option given by id or title. `-` abstains
+        #[structopt(required = true)]
+        scores: Vec<String>,
+        /// name shown next to the ballot. not needed for secret ballots
+        #[structopt(long, default_value = "")]
+        name: String,
+        /// file with the voter identity, created on the first vote
+        #[structopt(long, env = "SVT_IDENTITY", default_value = "svt-identity.json")]
+        identity: PathBuf,
+    },
+    /// prints a poll and its options
+    Show {
+        poll_id: String,
+        /// file with the voter identity, needed to see results that are only shown to voters
+        #[structopt(long, env = "SVT_IDENTITY", default_value = "svt-identity.json")]
+        identity: PathBuf,
+    },
+    /// prints the ranking of a poll
+    Results {
+        poll_id: String,
+        /// file with the voter identity, needed to see results that are only shown to voters
+        #[structopt(long, env = "SVT_IDENTITY", default_value = "svt-identity.json")]
+        identity: PathBuf,
+    },
+}
+
+/// `ClientError` can't be sent between threads, so only its message is kept for transport errors
+fn api_error(e: impl Into<ClientError>) -> anyhow::Error {
+    match e.into() {
+        ClientError::Api(e) => e.into(),
+        ClientError::Transport(e) => anyhow!("{e}"),
+    }
+}
+
+async fn connect(server: &str) -> anyhow::Result<ApiClient> {
+    jsonrpc_core_client::transports::http::connect::<ApiClient>(server)
+        .await
+        .map_err(api_error)
+        .with_context(|| format!("could not connect to {server}"))
+}
+
+fn load_identity(path: &Path) -> anyhow::Result<Option<VoterIdentity>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read {}", path.display()))?;
+    let identity = serde_json::from_str(&text)
+        .with_context(|| format!("invalid voter identity in {}", path.display()))?;
+    Ok(Some(identity))
+}
+
+/// the identity in `path`, or a new one from the server that is saved there
+async fn voter_identity(client: &ApiClient, path: &Path) -> anyhow::Result<VoterIdentity> {
+    if let Some(identity) = load_identity(path)? {
+        return Ok(identity);
+    }
+    let identity = client.create_voter().await.map_err(api_error)?;
+    std::fs::write(path, serde_json::to_string_pretty(&identity)?)
+        .with_context(|| format!("could not save the voter identity to {}", path.display()))?;
+    Ok(identity)
+}
+
+async fn get_poll(client: &ApiClient, poll_id: &str, identity: &Path) -> anyhow::Result<PollView> {
+    client
+        .get_poll(PublicPollId::from_str(poll_id), load_identity(identity)?)
+        .await
+        .map_err(api_error)
+}
+
+/// turns `<option>=<score>` arguments into the scores of a ballot
+fn parse_scores(
+    poll: &PollV2,
+    scores: &[String],
+) -> anyhow::Result<HashMap<PollOptionId, Option<f64>>> {
+    let mut votes = HashMap::new();
+    for arg in scores {
+        let (option, score) = arg
+            .rsplit_once('=')
+            .with_context(|| format!("expected <option>=<score>, got {arg:?}"))?;
+        let option = poll
+            .options
+            .iter()
+            .find(|o| o.id.to_str() == option)
+            .or_else(|| {
+                poll.options
+                    .iter()
+                    .find(|o| o.title.eq_ignore_ascii_case(option))
+            })
+            .with_context(|| format!("poll has no option {option:?}"))?;
+        let score = match score.trim() {
+            "-" => None,
+            score => Some(
+                score
+                    .parse::<f64>()
+                    .with_context(|| format!("invalid score {score:?}"))?,
+            ),
+        };
+        votes.insert(option.id.clone(), score);
+    }
+    Ok(votes)
+}
+
+fn option_title<'a>(poll: &'a PollV2, id: &'a PollOptionId) -> &'a str {
+    poll.options
+        .iter()
+        .find(|o| &o.id == id)
+        .map(|o| o.title.as_str())
+        .unwrap_or_else(|| id.to_str())
+}
+
+fn print_poll(view: &PollView) {
+    let poll = &view.poll;
+    let state = if poll.closed { "closed" } else { "open" };
+    println!("{} ({state}, {} ballots)", poll.title, ballot_count(view));
+    if !poll.description_text_markdown.is_empty() {
+        println!("\n{}\n", poll.description_text_markdown);
+    }
+    let scale = &poll.score_scale;
+    println!(
+        "scores from {} to {} in steps of {}{}",
+        scale.min,
+        scale.max,
+        scale.step,
+        if scale.allow_abstain {
+            ", abstaining allowed"
+        } else {
+            ""
+        }
+    );
+    for option in &poll.options {
+        println!("  {}\t{}", option.id.to_str(), option.title);
+    }
+}
+
+/// anonymous polls only send aggregates
+fn ballot_count(view: &PollView) -> usize {
+    view.aggregates
+        .as_ref()
+        .map_or(view.poll.votes.len(), |a| a.ballots)
+}
+
+fn print_results(view: &PollView) -> anyhow::Result<()> {
+    let poll = &view.poll;
+    if view.results_hidden {
+        bail!("the results of this poll are hidden until you voted or the poll is closed");
+    }
+    let result = match &poll.result {
+        Some(result) => result,
+        None => {
+            println!("no ballots yet");
+            return Ok(());
+        }
+    };
+    println!(
+        "{}, {} ballots, tallied by {}",
+        poll.title,
+        ballot_count(view),
+        result.method.name()
+    );
+    for (place, id) in result.ranking.iter().enumerate() {
+        let score = match result.scores.get(id).copied().flatten() {
+            Some(score) => format!("{score:.2}"),
+            None => "-".to_string(),
+        };
+        println!("{:>3}. {}\t{score}", place + 1, option_title(poll, id));
+    }
+    if let Some(runoff) = &result.runoff {
+        println!("runoff winner: {}", option_title(poll, &runoff.winner));
+    }
+    if let Some(committee) = &result.committee {
+        let winners: Vec<_> = committee
+            .winners
+            .iter()
+            .map(|id| option_title(poll, id))
+            .collect();
+        println!(
+            "elected by {}: {}",
+            committee.method.name(),
+            winners.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::from_args();
+    let client = connect(&args.server).await?;
+    match args.command {
+        Command::CreatePoll { from } => {
+            let text = std::fs::read_to_string(&from)
+                .with_context(|| format!("could not read {}", from.display()))?;
+            let file: PollFile = toml::from_str(&text)
+                .with_context(|| format!("invalid poll file {}", from.display()))?;
+            let created = client
+                .create_poll(CreatePoll::from(file))
+                .await
+                .map_err(api_error)?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&created)?);
+            } else {
+                println!("poll id: {}", created.poll.poll.id.to_str());
+                println!("admin secret: {}", created.admin_secret.to_str());
+            }
+        }
+        Command::Vote {
+            poll_id,
+            scores,
+            name,
+            identity,
+        } => {
+            let poll = get_poll(&client, &poll_id, &identity).await?.poll;
+            let votes = parse_scores(&poll, &scores)?;
+            let voter = voter_identity(&client, &identity).await?;
+            let ballot = ScoreVote {
+                user_id: voter.user_id,
+                user_name: name,
+                votes,
+            };
+            let view = client
+                .vote(poll.id, ballot, voter.token)
+                .await
+                .map_err(api_error)?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&view)?);
+            } else {
+                println!("ballot cast in {}", view.poll.title);
+            }
+        }
+        Command::Show { poll_id, identity } => {
+            let view = get_poll(&client, &poll_id, &identity).await?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&view)?);
+            } else {
+                print_poll(&view);
+            }
+        }
+        Command::Results { poll_id, identity } => {
+            let view = get_poll(&client, &poll_id, &identity).await?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&view.poll.result)?);
+            } else {
+                print_results(&view)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+//! the TOML description of a poll read by `svt create-poll --from`
+use common::{
+    CreatePoll, PollOption, PollOptionId, ProportionalMethod, ResultVisibility, ScoreScale,
+    TallyMethod,
+};
+use serde::Deserialize;
+
+/// everything but `title` and `options` can be left out
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PollFile {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub options: Vec<OptionFile>,
+    #[serde(default)]
+    pub score_scale: ScoreScale,
+    #[serde(default)]
+    pub tally_method: TallyMethod,
+    #[serde(default = "one_seat")]
+    pub seats: u32,
+    #[serde(default)]
+    pub proportional_method: ProportionalMethod,
+    /// unix timestamps in seconds
+    pub opens_at: Option<u64>,
+    pub closes_at: Option<u64>,
+    #[serde(default)]
+    pub result_visibility: ResultVisibility,
+    #[serde(default)]
+    pub anonymous: bool,
+}
+
+fn one_seat() -> u32 {
+    1
+}
+
+/// either just the title or a table with a description
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum OptionFile {
+    Title(String),
+    Full {
+        title: String,
+        #[serde(default)]
+        description: String,
+    },
+}
+
+impl From<PollFile> for CreatePoll {
+    fn from(file: PollFile) -> Self {
+        CreatePoll {
+            title: file.title,
+            description_text_markdown: file.description,
+            options: file
+                .options
+                .into_iter()
+                .enumerate()
+                .map(|(i, option)| {
+                    let (title, description) = match option {
+                        OptionFile::Title(title) => (title, String::new()),
+                        OptionFile::Full { title, description } => (title, description),
+                    };
+                    // numbered from 1 like the options created in the browser
+                    PollOption {
+                        id: PollOptionId::from_str(format!("{}", i + 1)),
+                        title,
+                        description_text_markdown: description,
+                    }
+                })
+                .collect(),
+            score_scale: file.score_scale,
+            tally_method: file.tally_method,
+            seats: file.seats,
+            proportional_method: file.proportional_method,
+            opens_at: file.opens_at,
+            closes_at: file.closes_at,
+            result_visibility: file.result_visibility,
+            anonymous: file.anonymous,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<PollFile, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    #[test]
+    fn settings_default_to_those_of_the_browser() {
+        let file = parse(
+            r#"
+            title = "Lunch"
+            options = ["Pizza", "Sushi"]
+            "#,
+        )
+        .unwrap();
+        let poll = CreatePoll::from(file);
+        assert_eq!(poll.title, "Lunch");
+        assert_eq!(poll.description_text_markdown, "");
+        assert_eq!(poll.score_scale, ScoreScale::default());
+        assert_eq!(poll.tally_method, TallyMethod::default());
+        assert_eq!(poll.seats, 1);
+        assert_eq!(poll.proportional_method, ProportionalMethod::default());
+        assert_eq!((poll.opens_at, poll.closes_at), (None, None));
+        assert_eq!(poll.result_visibility, ResultVisibility::default());
+        assert!(!poll.anonymous);
+    }
+
+    #[test]
+    fn options_are_numbered_from_one() {
+        let file = parse(
+            r#"
+            title = "Lunch"
+            options = [
+                "Pizza",
+                { title = "Sushi", description = "the place *downtown*" },
+                { title = "Salad" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let options = CreatePoll::from(file).options;
+        let ids: Vec<_> = options.iter().map(|o| o.id.to_str()).collect();
+        assert_eq!(ids, ["1", "2", "3"]);
+        let titles: Vec<_> = options.iter().map(|o| o.title.as_str()).collect();
+        assert_eq!(titles, ["Pizza", "Sushi", "Salad"]);
+        assert_eq!(options[0].description_text_markdown, "");
+        assert_eq!(options[1].description_text_markdown, "the place *downtown*");
+    }
+
+    #[test]
+    fn every_setting_is_read() {
+        let file = parse(
+            r#"
+            title = "Board"
+            description = "pick three"
+            options = ["A", "B", "C", "D"]
+            tally_method = "Star"
+            seats = 3
+            proportional_method = "SequentiallySpentScore"
+            opens_at = 1700000000
+            closes_at = 1800000000
+            result_visibility = "AfterClosing"
+            anonymous = true
+
+            [score_scale]
+            min = -2
+            max = 2
+            step = 0.5
+            allow_abstain = false
+            "#,
+        )
+        .unwrap();
+        let poll = CreatePoll::from(file);
+        assert_eq!(poll.description_text_markdown, "pick three");
+        assert_eq!(
+            poll.score_scale,
+            ScoreScale {
+                min: -2.0,
+                max: 2.0,
+                step: 0.5,
+                allow_abstain: false,
+            }
+        );
+        assert_eq!(poll.tally_method, TallyMethod::Star);
+        assert_eq!(poll.seats, 3);
+        assert_eq!(
+            poll.proportional_method,
+            ProportionalMethod::SequentiallySpentScore
+        );
+        assert_eq!(poll.opens_at, Some(1700000000));
+        assert_eq!(poll.closes_at, Some(1800000000));
+        assert_eq!(poll.result_visibility, ResultVisibility::AfterClosing);
+        assert!(poll.anonymous);
+    }
+
+    #[test]
+    fn rejects_incomplete_and_unknown_fields() {
+        assert!(parse(r#"title = "Lunch""#).is_err());
+        assert!(parse(r#"options = ["Pizza"]"#).is_err());
+        let typo = r#"
+            title = "Lunch"
+            options = ["Pizza"]
+            anonymus = true
+        "#;
+        assert!(parse(typo).is_err());
+    }
+}
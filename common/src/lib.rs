@@ -1,19 +1,69 @@
 //! defines the isomorphic code (common to both client and server)
-use std::collections::HashMap;
+use std::{collections::HashMap, future::Future};
 
 use jsonrpc_core::BoxFuture;
 use jsonrpc_derive::rpc;
 use serde::{Deserialize, Serialize};
 use std::result::Result;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+pub mod subscriptions;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
 pub struct PublicUserId(String);
 impl PublicUserId {
     pub fn from_str(str: impl Into<String>) -> PublicUserId {
         PublicUserId(str.into())
     }
+    pub fn to_str(&self) -> &str {
+        &self.0
+    }
+}
+/// secret that proves that a voter owns a `PublicUserId`. never shown to other users
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VoterToken(String);
+impl VoterToken {
+    pub fn new(str: impl Into<String>) -> VoterToken {
+        VoterToken(str.into())
+    }
+    pub fn to_str(&self) -> &str {
+        &self.0
+    }
+}
+impl std::str::FromStr for VoterToken {
+    type Err = std::convert::Infallible;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(VoterToken::new(str))
+    }
 }
+
+/// issued by the server once per voter, kept by the client
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VoterIdentity {
+    pub user_id: PublicUserId,
+    pub token: VoterToken,
+}
+
+/// secret that allows managing a poll, only known to the creator of the poll
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AdminSecret(String);
+impl AdminSecret {
+    pub fn new(str: impl Into<String>) -> AdminSecret {
+        AdminSecret(str.into())
+    }
+    pub fn to_str(&self) -> &str {
+        &self.0
+    }
+}
+impl std::str::FromStr for AdminSecret {
+    type Err = std::convert::Infallible;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(AdminSecret::new(str))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct PublicPollId(String);
 
 impl PublicPollId {
@@ -30,8 +80,12 @@ impl PollOptionId {
     pub fn from_str(str: String) -> PollOptionId {
         PollOptionId(str)
     }
+    pub fn to_str(&self) -> &str {
+        &self.0
+    }
 }
 
+/// the poll format of the first release, only read to upgrade old databases and archives
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PollV1 {
     pub id: PublicPollId,
@@ -42,6 +96,545 @@ pub struct PollV1 {
     pub result: Option<HashMap<PollOptionId, Option<f64>>>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PollV2 {
+    pub id: PublicPollId,
+    pub title: String,
+    pub description_text_markdown: String,
+    pub options: Vec<PollOption>,
+    pub score_scale: ScoreScale,
+    pub votes: Vec<ScoreVote>,
+    pub tally_method: TallyMethod,
+    /// number of options to elect. if greater than one, `proportional_method` picks the committee
+    pub seats: u32,
+    pub proportional_method: ProportionalMethod,
+    pub result: Option<PollResult>,
+    /// ballots that were replaced by a newer ballot of the same user or retracted, oldest first.
+    /// only sent to the poll admin
+    pub vote_history: Vec<SupersededVote>,
+    /// closed polls don't accept ballots
+    pub closed: bool,
+    /// unix timestamp in seconds before which no ballots are accepted
+    pub opens_at: Option<u64>,
+    /// unix timestamp in seconds at which the poll is closed automatically
+    pub closes_at: Option<u64>,
+    pub result_visibility: ResultVisibility,
+    /// secret ballot. the server never sends `votes` or `vote_history`, only
+    /// `PollView::aggregates`
+    pub anonymous: bool,
+}
+
+impl From<PollV1> for PollV2 {
+    /// polls of the first release get the default settings, whose score scale matches the vote
+    /// buttons of that release. their result was a plain map of means and is dropped, the
+    /// backend computes it again
+    fn from(poll: PollV1) -> Self {
+        PollV2 {
+            id: poll.id,
+            title: poll.title,
+            description_text_markdown: poll.description_text_markdown,
+            options: poll.options,
+            score_scale: ScoreScale::default(),
+            votes: poll.votes,
+            tally_method: TallyMethod::default(),
+            seats: 1,
+            proportional_method: ProportionalMethod::default(),
+            result: None,
+            vote_history: vec![],
+            closed: false,
+            opens_at: None,
+            closes_at: None,
+            result_visibility: ResultVisibility::default(),
+            anonymous: false,
+        }
+    }
+}
+
+/// a poll as the server sends it, with what it left out for the requester
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PollView {
+    pub poll: PollV2,
+    /// set when the server left out `votes` and `result` because of `result_visibility`
+    pub results_hidden: bool,
+    /// set instead of `votes` for anonymous polls
+    pub aggregates: Option<BallotAggregates>,
+}
+
+/// what is published about the ballots of an anonymous poll
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BallotAggregates {
+    /// number of ballots cast
+    pub ballots: usize,
+    pub options: HashMap<PollOptionId, OptionAggregate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OptionAggregate {
+    /// number of ballots per score, in the order of `ScoreScale::values`
+    pub distribution: Vec<usize>,
+    /// number of ballots that abstained on this option
+    pub abstentions: usize,
+    /// number of ballots that gave this option a score
+    pub count: usize,
+    /// mean of all scores given to this option
+    pub mean: Option<f64>,
+}
+
+/// who may see the ballots and the result of a poll
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultVisibility {
+    #[default]
+    Always,
+    /// only voters that have cast a ballot, or everyone once the poll is closed
+    AfterVoting,
+    /// everyone, but only once the poll is closed
+    AfterClosing,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SupersededVote {
+    pub vote: ScoreVote,
+    pub reason: SupersededReason,
+    /// unix timestamp in seconds
+    pub superseded_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupersededReason {
+    Replaced,
+    Retracted,
+    RemovedByAdmin,
+}
+
+/// errors of the rpc methods. sent as the `data` of the JSON-RPC error, with a stable error
+/// code per variant: 1xx invalid ballots, 2xx poll state, 3xx authorization, 5xx server side
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "error")]
+pub enum ApiError {
+    InvalidBallot(BallotError),
+    PollNotFound,
+    /// the poll has been closed and doesn't accept ballots anymore
+    PollClosed,
+    /// the poll doesn't accept ballots at this time because of its `opens_at` or `closes_at`
+    OutsideVotingPeriod {
+        opens_at: Option<u64>,
+        closes_at: Option<u64>,
+    },
+    /// the poll's `result_visibility` doesn't allow showing the results yet
+    ResultsHidden,
+    /// the poll to create or the edit of a poll is not valid
+    InvalidPoll {
+        reason: String,
+    },
+    /// the admin secret does not belong to the poll
+    Unauthorized,
+    StorageError {
+        message: String,
+    },
+}
+
+impl ApiError {
+    pub fn code(&self) -> i64 {
+        match self {
+            ApiError::InvalidBallot(e) => e.code(),
+            ApiError::PollNotFound => 200,
+            ApiError::PollClosed => 201,
+            ApiError::OutsideVotingPeriod { .. } => 202,
+            ApiError::ResultsHidden => 203,
+            ApiError::InvalidPoll { .. } => 204,
+            ApiError::Unauthorized => 300,
+            ApiError::StorageError { .. } => 500,
+        }
+    }
+
+    /// recovers the error from a JSON-RPC error returned by the server
+    pub fn from_rpc_error(e: &jsonrpc_core::Error) -> Option<ApiError> {
+        let api_error: ApiError = jsonrpc_core::serde_json::from_value(e.data.clone()?).ok()?;
+        match e.code {
+            jsonrpc_core::ErrorCode::ServerError(code) if code == api_error.code() => {
+                Some(api_error)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<BallotError> for ApiError {
+    fn from(e: BallotError) -> Self {
+        ApiError::InvalidBallot(e)
+    }
+}
+
+impl From<ApiError> for jsonrpc_core::Error {
+    fn from(e: ApiError) -> Self {
+        jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(e.code()),
+            message: e.to_string(),
+            data: jsonrpc_core::serde_json::to_value(&e).ok(),
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::InvalidBallot(e) => write!(f, "invalid ballot: {e}"),
+            ApiError::PollNotFound => write!(f, "poll not found"),
+            ApiError::PollClosed => write!(f, "poll is closed"),
+            ApiError::OutsideVotingPeriod { .. } => {
+                write!(f, "poll does not accept ballots at this time")
+            }
+            ApiError::ResultsHidden => write!(f, "the results of this poll are not visible yet"),
+            ApiError::InvalidPoll { reason } => write!(f, "invalid poll: {reason}"),
+            ApiError::Unauthorized => write!(f, "invalid admin secret"),
+            ApiError::StorageError { message } => write!(f, "storage error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// what the methods of `ApiClient` fail with, once decoded with `ClientError::from`
+#[derive(Debug)]
+pub enum ClientError {
+    /// the server handled the request and returned an error
+    Api(ApiError),
+    /// the request didn't reach the server or the response could not be understood
+    Transport(jsonrpc_core_client::RpcError),
+}
+
+impl From<jsonrpc_core_client::RpcError> for ClientError {
+    fn from(e: jsonrpc_core_client::RpcError) -> Self {
+        match &e {
+            jsonrpc_core_client::RpcError::JsonRpcError(rpc_error) => {
+                match ApiError::from_rpc_error(rpc_error) {
+                    Some(api_error) => ClientError::Api(api_error),
+                    None => ClientError::Transport(e),
+                }
+            }
+            _ => ClientError::Transport(e),
+        }
+    }
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Api(e) => write!(f, "{e}"),
+            ClientError::Transport(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// reasons for the server to reject a ballot, sent as `ApiError::InvalidBallot`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "reason")]
+pub enum BallotError {
+    EmptyUserName,
+    /// the ballot doesn't score any option
+    EmptyBallot,
+    /// the ballot scores an option that is not part of the poll
+    UnknownOption {
+        option: PollOptionId,
+    },
+    /// NaN or infinite score (sent as null)
+    NonFiniteScore {
+        option: PollOptionId,
+    },
+    ScoreOutOfScale {
+        option: PollOptionId,
+        score: f64,
+    },
+    ScoreNotOnStep {
+        option: PollOptionId,
+        score: f64,
+    },
+    /// the poll doesn't allow abstaining, but the option has no score
+    MissingScore {
+        option: PollOptionId,
+    },
+    /// the user has no ballot in the poll that could be updated or retracted
+    NoExistingBallot,
+    /// the voter token does not belong to the user id of the ballot
+    InvalidVoterToken,
+}
+
+impl BallotError {
+    pub fn code(&self) -> i64 {
+        match self {
+            BallotError::EmptyUserName => 100,
+            BallotError::EmptyBallot => 101,
+            BallotError::UnknownOption { .. } => 102,
+            BallotError::NonFiniteScore { .. } => 103,
+            BallotError::ScoreOutOfScale { .. } => 104,
+            BallotError::ScoreNotOnStep { .. } => 105,
+            BallotError::MissingScore { .. } => 106,
+            BallotError::NoExistingBallot => 107,
+            BallotError::InvalidVoterToken => 108,
+        }
+    }
+}
+
+impl std::fmt::Display for BallotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BallotError::EmptyUserName => write!(f, "user name must not be empty"),
+            BallotError::EmptyBallot => write!(f, "ballot does not score any option"),
+            BallotError::UnknownOption { option } => {
+                write!(f, "option {} is not part of the poll", option.0)
+            }
+            BallotError::NonFiniteScore { option } => {
+                write!(f, "score for option {} is not a finite number", option.0)
+            }
+            BallotError::ScoreOutOfScale { option, score } => {
+                write!(f, "score {score} for option {} is out of scale", option.0)
+            }
+            BallotError::ScoreNotOnStep { option, score } => {
+                write!(f, "score {score} for option {} is not on a step", option.0)
+            }
+            BallotError::MissingScore { option } => {
+                write!(f, "option {} needs a score", option.0)
+            }
+            BallotError::NoExistingBallot => write!(f, "user has not voted in this poll"),
+            BallotError::InvalidVoterToken => write!(f, "voter token is not valid for this user"),
+        }
+    }
+}
+
+impl std::error::Error for BallotError {}
+
+/// the scores a voter can give to each option: `min`, `min + step`, ..., `max`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ScoreScale {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+    /// whether a voter may leave an option without a score
+    pub allow_abstain: bool,
+}
+
+impl Default for ScoreScale {
+    fn default() -> Self {
+        ScoreScale {
+            min: 0.0,
+            max: 9.0,
+            step: 1.0,
+            allow_abstain: true,
+        }
+    }
+}
+
+impl ScoreScale {
+    /// upper bound for the number of scores on a scale, so the vote buttons stay usable
+    pub const MAX_STEPS: usize = 100;
+    /// allowance for rounding errors in scores, as a fraction of `step`
+    const TOLERANCE: f64 = 1e-9;
+
+    /// checks that the scale is finite, non-empty and that `max` is on a step
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.min.is_finite() && self.max.is_finite() && self.step.is_finite()) {
+            return Err("score scale must be finite".to_string());
+        }
+        if self.min >= self.max || self.step <= 0.0 {
+            return Err("score scale needs min < max and a positive step".to_string());
+        }
+        let steps = (self.max - self.min) / self.step;
+        if steps > Self::MAX_STEPS as f64 {
+            return Err(format!(
+                "score scale can have at most {} steps",
+                Self::MAX_STEPS
+            ));
+        }
+        if !self.contains(self.max) {
+            return Err("score scale max must be min plus a multiple of step".to_string());
+        }
+        Ok(())
+    }
+
+    /// whether `score` is finite, within the scale and on a step
+    pub fn contains(&self, score: f64) -> bool {
+        score.is_finite() && self.is_in_range(score) && self.is_on_step(score)
+    }
+
+    /// whether `score` is between `min` and `max`, up to rounding errors
+    pub fn is_in_range(&self, score: f64) -> bool {
+        let slack = self.step * Self::TOLERANCE;
+        score >= self.min - slack && score <= self.max + slack
+    }
+
+    /// whether `score` is one of `values`, up to rounding errors
+    pub fn is_on_step(&self, score: f64) -> bool {
+        let nearest = self.value_at(((score - self.min) / self.step).round());
+        let slack = (self.step * Self::TOLERANCE).max(score.abs() * f64::EPSILON * 4.0);
+        (score - nearest).abs() <= slack
+    }
+
+    /// all scores on the scale, lowest first
+    pub fn values(&self) -> Vec<f64> {
+        let steps = ((self.max - self.min) / self.step).round() as usize;
+        (0..=steps.min(Self::MAX_STEPS))
+            .map(|i| {
+                if i == steps {
+                    return self.max;
+                }
+                self.value_at(i as f64)
+            })
+            .collect()
+    }
+
+    /// `min` plus `steps` steps, rounded to the decimals of `min` and `step` so that a scale in
+    /// steps of 0.1 offers 0.3 and not 0.30000000000000004. `is_on_step` compares against the
+    /// same rounding, so every value passes it even if `step` has more decimals than are kept
+    fn value_at(&self, steps: f64) -> f64 {
+        let factor = 10f64.powi(decimals(self.min).max(decimals(self.step)));
+        ((self.min + steps * self.step) * factor).round() / factor
+    }
+
+    /// maps a score on this scale to [0, 1]
+    pub fn normalize(&self, score: f64) -> f64 {
+        ((score - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+    }
+}
+
+/// number of decimal places of `x`, at most 9
+fn decimals(x: f64) -> i32 {
+    (0..9)
+        .find(|&d| {
+            let scaled = x * 10f64.powi(d);
+            (scaled - scaled.round()).abs() < 1e-6
+        })
+        .unwrap_or(9)
+}
+
+/// how the scores of all ballots are aggregated into a single value per option
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum TallyMethod {
+    /// arithmetic mean of all non-abstaining scores
+    #[default]
+    Mean,
+    /// sum of all non-abstaining scores
+    Sum,
+    /// median of all non-abstaining scores
+    Median,
+    /// mean after dropping the `trim` fraction (0 <= trim < 0.5) of the lowest and of the highest scores
+    TrimmedMean { trim: f64 },
+    /// Score Then Automatic Runoff: the two options with the highest score sum go into a runoff,
+    /// where each ballot counts for the finalist it scored higher
+    Star,
+    /// Majority Judgment: options are ranked by their median grade,
+    /// ties are broken by repeatedly removing one median grade from each tied option
+    MajorityJudgment,
+}
+
+impl TallyMethod {
+    pub fn name(&self) -> String {
+        match self {
+            TallyMethod::Mean => "Mean".to_string(),
+            TallyMethod::Sum => "Sum".to_string(),
+            TallyMethod::Median => "Median".to_string(),
+            TallyMethod::TrimmedMean { trim } => format!("Trimmed mean ({:.0}%)", trim * 100.0),
+            TallyMethod::Star => "STAR".to_string(),
+            TallyMethod::MajorityJudgment => "Majority judgment".to_string(),
+        }
+    }
+}
+
+/// how a committee of several options is elected from the score ballots
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProportionalMethod {
+    /// Reweighted Range Voting: a ballot's weight is 1 / (1 + its normalized scores of all
+    /// already elected options)
+    #[default]
+    ReweightedRange,
+    /// Sequentially Spent Score: every ballot has a budget of 1. electing an option spends a quota
+    /// of (ballots / seats) from the ballots that support it, proportional to their scores
+    SequentiallySpentScore,
+}
+
+impl ProportionalMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProportionalMethod::ReweightedRange => "Reweighted range voting",
+            ProportionalMethod::SequentiallySpentScore => "Sequentially spent score",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PollResult {
+    /// the method that produced `scores`
+    pub method: TallyMethod,
+    /// aggregated score per option, None if nobody gave the option a score
+    pub scores: HashMap<PollOptionId, Option<f64>>,
+    /// all options of the poll, winner first
+    pub ranking: Vec<PollOptionId>,
+    /// the runoff round, only for STAR polls with at least two scored options
+    pub runoff: Option<StarRunoff>,
+    /// median grade and majority gauge per option, only for majority judgment polls
+    pub majority_judgment: Option<HashMap<PollOptionId, MajorityGrade>>,
+    /// the elected committee, only for polls with more than one seat
+    pub committee: Option<CommitteeResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommitteeResult {
+    pub method: ProportionalMethod,
+    /// the elected options in the order they were elected
+    pub winners: Vec<PollOptionId>,
+    /// one round per elected option
+    pub rounds: Vec<CommitteeRound>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommitteeRound {
+    /// the weight of every ballot in this round, in the same order as `PollV2.votes`
+    pub ballot_weights: Vec<f64>,
+    /// weighted sum of the normalized scores of every option that was not elected yet
+    pub weighted_scores: HashMap<PollOptionId, f64>,
+    pub elected: PollOptionId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MajorityGrade {
+    /// the lower median of all grades
+    pub median: f64,
+    /// share of grades strictly above the median
+    pub above: f64,
+    /// share of grades strictly below the median
+    pub below: f64,
+}
+
+impl MajorityGrade {
+    /// the signed majority gauge: the share above the median if it outweighs the share below, else
+    /// the negated share below. a higher gauge wins between options with the same median
+    pub fn gauge(&self) -> f64 {
+        if self.above > self.below {
+            self.above
+        } else {
+            -self.below
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StarRunoff {
+    pub finalists: Vec<StarFinalist>,
+    /// ballots that scored both finalists the same (or abstained on both)
+    pub no_preference: usize,
+    pub winner: PollOptionId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StarFinalist {
+    pub option: PollOptionId,
+    /// sum of all scores in the scoring round
+    pub score_sum: f64,
+    /// ballots that scored this finalist higher than the other one
+    pub preferred_by: usize,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct PollOption {
     pub id: PollOptionId,
@@ -56,9 +649,44 @@ pub struct ScoreVote {
     pub votes: HashMap<PollOptionId, Option<f64>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// head-to-head comparison of all options of a poll
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PairwiseResult {
+    /// the options in the order of the rows and columns of `matrix`
+    pub options: Vec<PollOptionId>,
+    /// `matrix[i][j]` is the number of ballots that scored option i higher than option j.
+    /// an abstention counts as lower than any score
+    pub matrix: Vec<Vec<usize>>,
+    /// the option that wins the head-to-head comparison against every other option, if any
+    pub condorcet_winner: Option<PollOptionId>,
+    /// the Schulze ordering, only set if there is no Condorcet winner.
+    /// winner first, options in the same group are tied
+    pub schulze_ranking: Option<Vec<Vec<PollOptionId>>>,
+}
+
+/// a poll as it is stored and archived. adding a version means adding a variant, a conversion
+/// from the previous version, a case in `into_latest` and a migration in the backend
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Poll {
     V1(PollV1),
+    V2(Box<PollV2>),
+}
+
+impl Poll {
+    pub fn id(&self) -> &PublicPollId {
+        match self {
+            Poll::V1(poll) => &poll.id,
+            Poll::V2(poll) => &poll.id,
+        }
+    }
+
+    /// upgrades a poll of any version to the newest one
+    pub fn into_latest(self) -> PollV2 {
+        match self {
+            Poll::V1(poll) => poll.into(),
+            Poll::V2(poll) => *poll,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -66,6 +694,72 @@ pub struct CreatePoll {
     pub title: String,
     pub description_text_markdown: String,
     pub options: Vec<PollOption>,
+    pub score_scale: ScoreScale,
+    pub tally_method: TallyMethod,
+    pub seats: u32,
+    pub proportional_method: ProportionalMethod,
+    pub opens_at: Option<u64>,
+    pub closes_at: Option<u64>,
+    pub result_visibility: ResultVisibility,
+    pub anonymous: bool,
+}
+
+/// what `list_polls` returns for every poll, without the ballots
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PollSummary {
+    pub id: PublicPollId,
+    pub title: String,
+    pub closed: bool,
+    pub opens_at: Option<u64>,
+    pub closes_at: Option<u64>,
+    /// number of ballots cast
+    pub ballots: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PollStateFilter {
+    #[default]
+    Any,
+    Open,
+    Closed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ListPolls {
+    /// `next_cursor` of the previous page, `None` for the first page
+    pub cursor: Option<PublicPollId>,
+    /// maximum number of polls to return, at most `ListPolls::MAX_LIMIT`
+    pub limit: u32,
+    /// only polls whose title contains this text, ignoring case
+    pub title_contains: Option<String>,
+    pub state: PollStateFilter,
+}
+
+impl ListPolls {
+    pub const MAX_LIMIT: u32 = 100;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PollList {
+    pub polls: Vec<PollSummary>,
+    /// set if there are more polls matching the query
+    pub next_cursor: Option<PublicPollId>,
+}
+
+/// returned once when a poll is created. the admin secret can't be retrieved later
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatedPoll {
+    pub poll: PollView,
+    pub admin_secret: AdminSecret,
+}
+
+/// changes to an existing poll. fields that are None stay unchanged
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EditPoll {
+    pub title: Option<String>,
+    pub description_text_markdown: Option<String>,
+    /// options to append. existing options can't be changed since ballots refer to them
+    pub add_options: Vec<PollOption>,
 }
 
 #[rpc]
@@ -78,17 +772,284 @@ where
     fn add(&self, a: u64, b: u64) -> Result<u64, ErrT>;
 
     #[rpc(name = "create_poll")]
-    fn create_poll(&self, poll: CreatePoll) -> Result<Poll, ErrT>;
+    fn create_poll(&self, poll: CreatePoll) -> Result<CreatedPoll, ErrT>;
 
+    /// `votes` and `result` are left out unless the poll's `result_visibility` allows them.
+    /// the voter identity is needed to see the results of `AfterVoting` polls
     #[rpc(name = "get_poll")]
-    fn get_poll(&self, poll_id: PublicPollId) -> Result<Poll, ErrT>;
+    fn get_poll(
+        &self,
+        poll_id: PublicPollId,
+        voter: Option<VoterIdentity>,
+    ) -> Result<PollView, ErrT>;
 
+    /// issues a new voter identity. the token has to be sent along with every ballot
+    #[rpc(name = "create_voter")]
+    fn create_voter(&self) -> Result<VoterIdentity, ErrT>;
+
+    /// casts a ballot. replaces the earlier ballot if the user has already voted
     #[rpc(name = "vote")]
-    fn vote(&self, poll_id: PublicPollId, vote: ScoreVote) -> Result<Poll, ErrT>;
+    fn vote(
+        &self,
+        poll_id: PublicPollId,
+        vote: ScoreVote,
+        token: VoterToken,
+    ) -> Result<PollView, ErrT>;
+
+    /// replaces the earlier ballot of the user, fails if there is none
+    #[rpc(name = "update_vote")]
+    fn update_vote(
+        &self,
+        poll_id: PublicPollId,
+        vote: ScoreVote,
+        token: VoterToken,
+    ) -> Result<PollView, ErrT>;
+
+    /// removes the ballot of the user from the tally
+    #[rpc(name = "retract_vote")]
+    fn retract_vote(
+        &self,
+        poll_id: PublicPollId,
+        user_id: PublicUserId,
+        token: VoterToken,
+    ) -> Result<PollView, ErrT>;
+
+    /// summaries of the polls matching `query`, one page at a time
+    #[rpc(name = "list_polls")]
+    fn list_polls(&self, query: ListPolls) -> Result<PollList, ErrT>;
+
+    /// compares every pair of options by how many ballots scored one above the other
+    /// fails if the results of the poll are not visible, see `get_poll`
+    #[rpc(name = "get_pairwise")]
+    fn get_pairwise(
+        &self,
+        poll_id: PublicPollId,
+        voter: Option<VoterIdentity>,
+    ) -> Result<PairwiseResult, ErrT>;
+
+    /// like `get_poll`, but includes the vote history
+    #[rpc(name = "get_poll_admin")]
+    fn get_poll_admin(&self, poll_id: PublicPollId, secret: AdminSecret) -> Result<PollView, ErrT>;
+
+    #[rpc(name = "close_poll")]
+    fn close_poll(&self, poll_id: PublicPollId, secret: AdminSecret) -> Result<PollView, ErrT>;
+
+    #[rpc(name = "reopen_poll")]
+    fn reopen_poll(&self, poll_id: PublicPollId, secret: AdminSecret) -> Result<PollView, ErrT>;
+
+    #[rpc(name = "edit_poll")]
+    fn edit_poll(
+        &self,
+        poll_id: PublicPollId,
+        secret: AdminSecret,
+        edit: EditPoll,
+    ) -> Result<PollView, ErrT>;
+
+    #[rpc(name = "delete_poll")]
+    fn delete_poll(&self, poll_id: PublicPollId, secret: AdminSecret) -> Result<(), ErrT>;
+
+    /// removes the ballot of a user from the tally. it is kept in the vote history. works on
+    /// closed polls too, so the admin can still take out ballots that shouldn't count
+    #[rpc(name = "remove_vote")]
+    fn remove_vote(
+        &self,
+        poll_id: PublicPollId,
+        secret: AdminSecret,
+        user_id: PublicUserId,
+    ) -> Result<PollView, ErrT>;
 
     /// Performs asynchronous operation
     #[rpc(name = "callAsync")]
     fn call(&self, a: u64) -> BoxFuture<Result<String, ErrT>>;
 }
 
-pub type ApiClient = gen_client::Client<jsonrpc_core::types::error::Error>; // todo: a specific error type can't be preserved apparently
+/// client of `Rpc`. the generated client fails with `RpcError`, this one decodes the `ApiError`
+/// in it
+#[derive(Clone)]
+pub struct ApiClient {
+    rpc: gen_client::Client<ApiError>,
+}
+
+impl From<jsonrpc_core_client::RpcChannel> for ApiClient {
+    fn from(channel: jsonrpc_core_client::RpcChannel) -> Self {
+        ApiClient {
+            rpc: channel.into(),
+        }
+    }
+}
+
+async fn decode<T>(
+    call: impl Future<Output = jsonrpc_core_client::RpcResult<T>>,
+) -> Result<T, ClientError> {
+    call.await.map_err(ClientError::from)
+}
+
+/// methods with the arguments of the `Rpc` method of the same name
+macro_rules! decoding_methods {
+    ($($name:ident($($arg:ident: $arg_type:ty),*) -> $returns:ty;)*) => {
+        $(
+            pub fn $name(
+                &self,
+                $($arg: $arg_type),*
+            ) -> impl Future<Output = Result<$returns, ClientError>> {
+                decode(self.rpc.$name($($arg),*))
+            }
+        )*
+    };
+}
+
+impl ApiClient {
+    decoding_methods! {
+        add(a: u64, b: u64) -> u64;
+        create_poll(poll: CreatePoll) -> CreatedPoll;
+        get_poll(poll_id: PublicPollId, voter: Option<VoterIdentity>) -> PollView;
+        create_voter() -> VoterIdentity;
+        vote(poll_id: PublicPollId, vote: ScoreVote, token: VoterToken) -> PollView;
+        update_vote(poll_id: PublicPollId, vote: ScoreVote, token: VoterToken) -> PollView;
+        retract_vote(poll_id: PublicPollId, user_id: PublicUserId, token: VoterToken) -> PollView;
+        list_polls(query: ListPolls) -> PollList;
+        get_pairwise(poll_id: PublicPollId, voter: Option<VoterIdentity>) -> PairwiseResult;
+        get_poll_admin(poll_id: PublicPollId, secret: AdminSecret) -> PollView;
+        close_poll(poll_id: PublicPollId, secret: AdminSecret) -> PollView;
+        reopen_poll(poll_id: PublicPollId, secret: AdminSecret) -> PollView;
+        edit_poll(poll_id: PublicPollId, secret: AdminSecret, edit: EditPoll) -> PollView;
+        delete_poll(poll_id: PublicPollId, secret: AdminSecret) -> ();
+        remove_vote(poll_id: PublicPollId, secret: AdminSecret, user_id: PublicUserId) -> PollView;
+        call(a: u64) -> String;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale(min: f64, max: f64, step: f64) -> ScoreScale {
+        ScoreScale {
+            min,
+            max,
+            step,
+            allow_abstain: true,
+        }
+    }
+
+    #[test]
+    fn values_are_rounded_to_the_step() {
+        let tenths = scale(0.0, 0.5, 0.1);
+        assert_eq!(tenths.values(), vec![0.0, 0.1, 0.2, 0.3, 0.4, 0.5]);
+        assert_eq!(scale(0.0, 0.3, 0.1).values().last(), Some(&0.3));
+        assert_eq!(
+            scale(-1.0, 1.0, 0.25).values(),
+            vec![-1.0, -0.75, -0.5, -0.25, 0.0, 0.25, 0.5, 0.75, 1.0]
+        );
+        assert_eq!(scale(0.5, 2.5, 1.0).values(), vec![0.5, 1.5, 2.5]);
+    }
+
+    #[test]
+    fn every_value_is_on_the_scale() {
+        for scale in [
+            scale(0.0, 0.3, 0.1),
+            scale(0.1, 0.7, 0.2),
+            scale(-0.3, 0.3, 0.1),
+            ScoreScale::default(),
+        ] {
+            assert!(scale.validate().is_ok());
+            for value in scale.values() {
+                assert!(scale.contains(value), "{value} not on {scale:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn values_of_awkward_steps_are_on_the_scale() {
+        for scale in [
+            scale(0.0, 1.0, 0.3333333333),
+            scale(0.0, 1.0, 1.0 / 3.0),
+            scale(0.0, 2.0, 2.0 / 3.0),
+            scale(0.0, 2.1, 0.7),
+            scale(-1.0, 1.0, 0.2),
+            scale(0.0, 1.0, 0.01),
+            scale(1000.0, 1000.05, 0.001),
+            scale(0.1, 0.1 + 7.0 * 0.07, 0.07),
+        ] {
+            assert!(scale.validate().is_ok(), "{scale:?} is invalid");
+            for value in scale.values() {
+                assert!(scale.contains(value), "{value} not on {scale:?}");
+            }
+        }
+        let thirds = scale(0.0, 1.0, 0.3333333333);
+        assert_eq!(thirds.values(), vec![0.0, 0.333333333, 0.666666667, 1.0]);
+        assert!(!thirds.contains(0.5));
+        assert!(!thirds.contains(0.6666));
+    }
+
+    #[test]
+    fn api_errors_survive_the_trip_through_json_rpc() {
+        let option = || PollOptionId::from_str("a".to_string());
+        let ballot_errors = [
+            BallotError::EmptyUserName,
+            BallotError::EmptyBallot,
+            BallotError::UnknownOption { option: option() },
+            BallotError::NonFiniteScore { option: option() },
+            BallotError::ScoreOutOfScale {
+                option: option(),
+                score: 11.0,
+            },
+            BallotError::ScoreNotOnStep {
+                option: option(),
+                score: 0.5,
+            },
+            BallotError::MissingScore { option: option() },
+            BallotError::NoExistingBallot,
+            BallotError::InvalidVoterToken,
+        ];
+        let errors = ballot_errors.into_iter().map(ApiError::from).chain([
+            ApiError::PollNotFound,
+            ApiError::PollClosed,
+            ApiError::OutsideVotingPeriod {
+                opens_at: Some(1),
+                closes_at: None,
+            },
+            ApiError::ResultsHidden,
+            ApiError::InvalidPoll {
+                reason: "no options".to_string(),
+            },
+            ApiError::Unauthorized,
+            ApiError::StorageError {
+                message: "disk full".to_string(),
+            },
+        ]);
+        let mut codes = std::collections::HashSet::new();
+        for error in errors {
+            assert!(codes.insert(error.code()), "{error:?} reuses its code");
+            let rpc_error = jsonrpc_core::Error::from(error.clone());
+            assert_eq!(
+                rpc_error.code,
+                jsonrpc_core::ErrorCode::ServerError(error.code())
+            );
+            assert_eq!(ApiError::from_rpc_error(&rpc_error), Some(error.clone()));
+            let client_error = jsonrpc_core_client::RpcError::JsonRpcError(rpc_error);
+            match ClientError::from(client_error) {
+                ClientError::Api(decoded) => assert_eq!(decoded, error),
+                ClientError::Transport(e) => panic!("{error:?} not decoded: {e}"),
+            }
+        }
+        assert_eq!(codes.len(), 16);
+
+        // only errors of the server carry an `ApiError`
+        let mut foreign = jsonrpc_core::Error::from(ApiError::PollClosed);
+        foreign.code = jsonrpc_core::ErrorCode::InvalidParams;
+        assert_eq!(ApiError::from_rpc_error(&foreign), None);
+        assert_eq!(
+            ApiError::from_rpc_error(&jsonrpc_core::Error::internal_error()),
+            None
+        );
+    }
+
+    #[test]
+    fn range_check_tolerates_rounding_errors_only() {
+        let scale = scale(0.0, 0.3, 0.1);
+        assert!(scale.is_in_range(0.1 * 3.0));
+        assert!(!scale.is_in_range(0.31));
+        assert!(!scale.is_in_range(-0.01));
+    }
+}
@@ -0,0 +1,28 @@
+//! live updates of polls. subscriptions need a persistent connection, so they are only
+//! served on the websocket endpoint
+use jsonrpc_core::Result;
+use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{typed::Subscriber, SubscriptionId};
+
+use crate::{PollView, PublicPollId, VoterIdentity};
+
+/// name of the notifications sent to poll subscribers
+pub const POLL_NOTIFICATION: &str = "poll";
+
+#[rpc]
+pub trait PollSubscriptions {
+    type Metadata;
+
+    /// sends the poll as `get_poll` would every time it changes, e.g. when a ballot is cast
+    #[pubsub(subscription = "poll", subscribe, name = "subscribe_poll")]
+    fn subscribe_poll(
+        &self,
+        meta: Self::Metadata,
+        subscriber: Subscriber<PollView>,
+        poll_id: PublicPollId,
+        voter: Option<VoterIdentity>,
+    );
+
+    #[pubsub(subscription = "poll", unsubscribe, name = "unsubscribe_poll")]
+    fn unsubscribe_poll(&self, meta: Option<Self::Metadata>, id: SubscriptionId) -> Result<bool>;
+}
@@ -1,9 +1,197 @@
-use jsonrpc_core_client::transports::wasmhttp;
+//! finding the server and talking to it. a `config.json` next to the page can name the
+//! endpoints, otherwise the HTTP endpoint is the origin of the page, like when the backend
+//! serves the frontend
+use std::{cell::RefCell, future::Future, rc::Rc};
 
-pub async fn connect() -> common::ApiClient {
-    let (client, receiver_task) = wasmhttp::connect::<common::ApiClient>("http://localhost:3030/")
+use common::{ApiClient, ClientError};
+use jsonrpc_core::serde_json::{self, Value};
+use jsonrpc_core_client::{transports::wasmhttp, RpcError};
+use sycamore::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Response;
+
+const CONFIG_URL: &str = "/config.json";
+/// the default `ws_listen` port of the backend
+const DEFAULT_WS_PORT: u16 = 3031;
+/// how long `call_idempotent` waits before each retry
+const RETRY_DELAYS_MS: &[u32] = &[250, 1000, 4000];
+
+#[derive(Debug)]
+pub struct Endpoints {
+    pub api_url: String,
+    pub ws_url: String,
+}
+
+thread_local! {
+    static ENDPOINTS: RefCell<Option<Rc<Endpoints>>> = RefCell::new(None);
+}
+
+/// the endpoints of the server. the config file is only fetched once
+pub async fn endpoints() -> Rc<Endpoints> {
+    if let Some(endpoints) = ENDPOINTS.with(|e| e.borrow().clone()) {
+        return endpoints;
+    }
+    let config = match fetch_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("ignoring {}: {}", CONFIG_URL, e);
+            None
+        }
+    };
+    let string = |key: &str| {
+        config
+            .as_ref()
+            .and_then(|c| c[key].as_str())
+            .map(str::to_string)
+    };
+    let endpoints = Rc::new(Endpoints {
+        api_url: string("api_url").unwrap_or_else(default_api_url),
+        ws_url: string("ws_url").unwrap_or_else(default_ws_url),
+    });
+    log::info!("using {:?}", endpoints);
+    ENDPOINTS.with(|e| *e.borrow_mut() = Some(endpoints.clone()));
+    endpoints
+}
+
+/// the parsed config file, `None` if there is none
+async fn fetch_config() -> Result<Option<Value>, String> {
+    let window = web_sys::window().ok_or("no window")?;
+    let response = JsFuture::from(window.fetch_with_str(CONFIG_URL))
         .await
-        .expect("fooooo");
+        .map_err(|e| format!("{:?}", e))?
+        .dyn_into::<Response>()
+        .map_err(|e| format!("{:?}", e))?;
+    if !response.ok() {
+        return Ok(None);
+    }
+    let text = JsFuture::from(response.text().map_err(|e| format!("{:?}", e))?)
+        .await
+        .map_err(|e| format!("{:?}", e))?
+        .as_string()
+        .unwrap_or_default();
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+fn default_api_url() -> String {
+    web_sys::window()
+        .and_then(|w| w.location().origin().ok())
+        .map(|origin| format!("{origin}/"))
+        .unwrap_or_else(|| "http://localhost:3030/".to_string())
+}
+
+fn default_ws_url() -> String {
+    let location = web_sys::window().map(|w| w.location());
+    let secure = location
+        .as_ref()
+        .and_then(|l| l.protocol().ok())
+        .is_some_and(|p| p == "https:");
+    let host = location
+        .and_then(|l| l.hostname().ok())
+        .unwrap_or_else(|| "localhost".to_string());
+    let scheme = if secure { "wss" } else { "ws" };
+    format!("{scheme}://{host}:{DEFAULT_WS_PORT}/")
+}
+
+async fn connect() -> Result<ApiClient, ClientError> {
+    let endpoints = endpoints().await;
+    let (client, receiver_task) = wasmhttp::connect::<ApiClient>(&endpoints.api_url).await?;
     wasm_bindgen_futures::spawn_local(receiver_task);
-    client
+    Ok(client)
+}
+
+/// the connection shared by all pages, provided as context by `App`
+#[derive(Clone)]
+pub struct SharedClient {
+    client: Rc<RefCell<Option<ApiClient>>>,
+    /// number of requests that haven't finished yet
+    pub pending: RcSignal<u32>,
+    /// why the server could not be reached. cleared by the next successful request
+    pub connection_problem: RcSignal<Option<String>>,
+}
+
+/// counts a request as pending until it is dropped
+struct PendingRequest(RcSignal<u32>);
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        *self.0.modify() -= 1;
+    }
+}
+
+/// the request didn't get an answer from the server. a JSON-RPC error is an answer
+fn is_unreachable(e: &ClientError) -> bool {
+    matches!(e, ClientError::Transport(e) if !matches!(e, RpcError::JsonRpcError(_)))
+}
+
+impl SharedClient {
+    pub fn new() -> SharedClient {
+        SharedClient {
+            client: Rc::new(RefCell::new(None)),
+            pending: create_rc_signal(0),
+            connection_problem: create_rc_signal(None),
+        }
+    }
+
+    /// the cached client, connecting on first use
+    async fn client(&self) -> Result<ApiClient, ClientError> {
+        if let Some(client) = self.client.borrow().clone() {
+            return Ok(client);
+        }
+        let client = connect().await?;
+        *self.client.borrow_mut() = Some(client.clone());
+        Ok(client)
+    }
+
+    /// runs `f` once. for calls that must not be repeated, like casting a ballot
+    pub async fn call<T, F, Fut>(&self, f: F) -> Result<T, ClientError>
+    where
+        F: FnOnce(ApiClient) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        *self.pending.modify() += 1;
+        let _pending = PendingRequest(self.pending.clone());
+        let result = match self.client().await {
+            Ok(client) => f(client).await,
+            Err(e) => Err(e),
+        };
+        match &result {
+            Err(e) if is_unreachable(e) => {
+                // the next call connects again
+                self.client.borrow_mut().take();
+                self.connection_problem.set(Some(e.to_string()));
+            }
+            _ if self.connection_problem.get().is_some() => self.connection_problem.set(None),
+            _ => {}
+        }
+        result
+    }
+
+    /// runs `f` until the server answers, waiting longer before each retry. only for calls
+    /// that can safely be repeated, like `get_poll`
+    pub async fn call_idempotent<T, F, Fut>(&self, f: F) -> Result<T, ClientError>
+    where
+        F: Fn(ApiClient) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut delays = RETRY_DELAYS_MS.iter();
+        loop {
+            let result = self.call(&f).await;
+            match (&result, delays.next()) {
+                (Err(e), Some(delay)) if is_unreachable(e) => {
+                    log::warn!("retrying in {delay} ms: {e}");
+                    gloo_timers::future::TimeoutFuture::new(*delay).await;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+impl Default for SharedClient {
+    fn default() -> Self {
+        SharedClient::new()
+    }
 }
@@ -0,0 +1,102 @@
+//! live updates of polls over the websocket endpoint of the backend
+use std::{cell::RefCell, rc::Rc};
+
+use common::{subscriptions::POLL_NOTIFICATION, PollView, PublicPollId, VoterIdentity};
+use jsonrpc_core::serde_json::{self, json, Value};
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+use web_sys::{MessageEvent, WebSocket};
+
+const SUBSCRIBE_REQUEST_ID: u64 = 1;
+
+/// an active `subscribe_poll` subscription. unsubscribes when dropped
+pub struct PollSubscription {
+    socket: WebSocket,
+    subscription_id: Rc<RefCell<Option<Value>>>,
+    _on_open: Closure<dyn FnMut()>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+/// calls `on_update` with the new state of the poll every time it changes
+pub fn subscribe_poll(
+    ws_url: &str,
+    poll_id: PublicPollId,
+    voter: Option<VoterIdentity>,
+    on_update: impl Fn(PollView) + 'static,
+) -> Result<PollSubscription, JsValue> {
+    let socket = WebSocket::new(ws_url)?;
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": SUBSCRIBE_REQUEST_ID,
+        "method": "subscribe_poll",
+        "params": [poll_id, voter],
+    })
+    .to_string();
+    let on_open = {
+        let socket = socket.clone();
+        Closure::wrap(Box::new(move || {
+            if let Err(e) = socket.send_with_str(&request) {
+                log::warn!("could not subscribe to poll: {:?}", e);
+            }
+        }) as Box<dyn FnMut()>)
+    };
+    socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+
+    let subscription_id = Rc::new(RefCell::new(None));
+    let on_message = {
+        let subscription_id = subscription_id.clone();
+        Closure::wrap(Box::new(move |event: MessageEvent| {
+            let message = match event.data().as_string() {
+                Some(text) => serde_json::from_str::<Value>(&text),
+                None => return,
+            };
+            let message = match message {
+                Ok(message) => message,
+                Err(e) => {
+                    log::warn!("invalid message from server: {}", e);
+                    return;
+                }
+            };
+            if message["id"] == SUBSCRIBE_REQUEST_ID {
+                if let Some(error) = message.get("error") {
+                    log::warn!("could not subscribe to poll: {}", error);
+                }
+                *subscription_id.borrow_mut() = message.get("result").cloned();
+            } else if message["method"] == POLL_NOTIFICATION {
+                // the last notification of a deleted poll
+                if let Some(error) = message["params"].get("error") {
+                    log::info!("poll is no longer available: {}", error);
+                    return;
+                }
+                match serde_json::from_value::<PollView>(message["params"]["result"].clone()) {
+                    Ok(poll) => on_update(poll),
+                    Err(e) => log::warn!("invalid poll update: {}", e),
+                }
+            }
+        }) as Box<dyn FnMut(MessageEvent)>)
+    };
+    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+    Ok(PollSubscription {
+        socket,
+        subscription_id,
+        _on_open: on_open,
+        _on_message: on_message,
+    })
+}
+
+impl Drop for PollSubscription {
+    fn drop(&mut self) {
+        self.socket.set_onopen(None);
+        self.socket.set_onmessage(None);
+        if let Some(id) = self.subscription_id.borrow_mut().take() {
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": SUBSCRIBE_REQUEST_ID + 1,
+                "method": "unsubscribe_poll",
+                "params": [id],
+            });
+            let _ = self.socket.send_with_str(&request.to_string());
+        }
+        let _ = self.socket.close();
+    }
+}
@@ -1,26 +1,34 @@
+mod api_client;
+mod live_updates;
+mod markdown;
+
 use std::collections::HashMap;
 
+use api_client::SharedClient;
 use common::{
-    CreatePoll, Poll, PollOption, PollOptionId, PollV1, PublicPollId, PublicUserId, ScoreVote,
+    AdminSecret, ApiError, BallotAggregates, BallotError, ClientError, CommitteeResult, CreatePoll,
+    EditPoll, ListPolls, MajorityGrade, PairwiseResult, PollOption, PollOptionId, PollStateFilter,
+    PollSummary, PollV2, PollView, ProportionalMethod, PublicPollId, PublicUserId,
+    ResultVisibility, ScoreScale, ScoreVote, StarRunoff, SupersededReason, SupersededVote,
+    TallyMethod, VoterIdentity,
 };
-use jsonrpc_core_client::{transports::wasmhttp, RpcError};
+use jsonrpc_core::serde_json;
+use markdown::Markdown;
 use sycamore::prelude::*;
 use sycamore_router::{navigate, HistoryIntegration, Route, Router};
 
-pub async fn connect() -> common::ApiClient {
-    let (client, receiver_task) = wasmhttp::connect::<common::ApiClient>("http://localhost:3030/")
-        .await
-        .expect("shouldn't fail");
-    wasm_bindgen_futures::spawn_local(receiver_task);
-    client
-}
-
 #[derive(Route)]
 enum AppRoutes {
     #[to("/")]
     CreatePollFonk,
+    #[to("/polls")]
+    PollDirectory,
     #[to("/poll/<poll_id>")]
     ViewPoll { poll_id: String },
+    #[to("/poll/<poll_id>/pairwise")]
+    ViewPairwise { poll_id: String },
+    #[to("/poll/<poll_id>/admin/<secret>")]
+    AdminPoll { poll_id: String, secret: String },
     #[not_found]
     NotFound,
 }
@@ -29,6 +37,7 @@ enum AppRoutes {
 struct EditPollOption {
     id: RcSignal<i32>,
     title: RcSignal<String>,
+    description: RcSignal<String>,
 }
 
 /*{
@@ -63,6 +72,11 @@ fn InputThong<'a, G: Html>(cx: Scope<'a>, p: MProp<'a>) -> View<G> {
                     button(class="button is-warning", on:click=move |_e| p.p.modify().retain(|e| *e.id.get() != id)) { "Remove" }
                 }
             }
+            div(class="field") {
+                div(class="control") {
+                    textarea(class="textarea", rows="2", bind:value=flonk.description, placeholder="Description (markdown, optional)")
+                }
+            }
         }
     }
 }
@@ -76,20 +90,56 @@ fn CreatePoll<G: Html>(cx: Scope) -> View<G> {
         create_rc_signal(id)
     };
 
-    /*create_effect(cx, move ||{
-        let sum = sum.clone();
-        wasm_bindgen_futures::spawn_local(async move {
-            let client = connect().await; // todo: connect only once
-            sum.set(client.add(1, 2).await.expect("todo: handle"));
-        });
-    });*/
-
     let poll_title = create_signal(cx, String::new());
     let poll_description = create_signal(cx, String::new());
+    let tally_method_name = create_signal(cx, "mean".to_string());
+    let trim_percent = create_signal(cx, "10".to_string());
+    let tally_method = create_memo(cx, || match tally_method_name.get().as_str() {
+        "sum" => TallyMethod::Sum,
+        "median" => TallyMethod::Median,
+        "star" => TallyMethod::Star,
+        "majority_judgment" => TallyMethod::MajorityJudgment,
+        "trimmed_mean" => TallyMethod::TrimmedMean {
+            trim: trim_percent.get().parse::<f64>().unwrap_or(0.0) / 100.0,
+        },
+        _ => TallyMethod::Mean,
+    });
+    let scale_min = create_signal(cx, "0".to_string());
+    let scale_max = create_signal(cx, "9".to_string());
+    let scale_step = create_signal(cx, "1".to_string());
+    let allow_abstain = create_signal(cx, true);
+    let anonymous = create_signal(cx, false);
+    let score_scale = create_memo(cx, || {
+        let default = ScoreScale::default();
+        ScoreScale {
+            min: scale_min.get().parse().unwrap_or(default.min),
+            max: scale_max.get().parse().unwrap_or(default.max),
+            step: scale_step.get().parse().unwrap_or(default.step),
+            allow_abstain: *allow_abstain.get(),
+        }
+    });
+    let opens_at_text = create_signal(cx, String::new());
+    let closes_at_text = create_signal(cx, String::new());
+    let opens_at = create_memo(cx, || parse_datetime_local(&opens_at_text.get()));
+    let closes_at = create_memo(cx, || parse_datetime_local(&closes_at_text.get()));
+    let seats_text = create_signal(cx, "1".to_string());
+    let seats = create_memo(cx, || seats_text.get().parse::<u32>().unwrap_or(1).max(1));
+    let proportional_method_name = create_signal(cx, "rrv".to_string());
+    let proportional_method = create_memo(cx, || match proportional_method_name.get().as_str() {
+        "sss" => ProportionalMethod::SequentiallySpentScore,
+        _ => ProportionalMethod::ReweightedRange,
+    });
+    let result_visibility_name = create_signal(cx, "always".to_string());
+    let result_visibility = create_memo(cx, || match result_visibility_name.get().as_str() {
+        "after_voting" => ResultVisibility::AfterVoting,
+        "after_closing" => ResultVisibility::AfterClosing,
+        _ => ResultVisibility::Always,
+    });
 
     let poll_options: RcSignal<Vec<EditPollOption>> = create_rc_signal(vec![EditPollOption {
         id: new_id(),
         title: create_rc_signal(String::new()),
+        description: create_rc_signal(String::new()),
     }]);
     let poll_op_ref = create_ref(cx, poll_options.clone());
 
@@ -97,6 +147,7 @@ fn CreatePoll<G: Html>(cx: Scope) -> View<G> {
         poll_op_ref.modify().push(EditPollOption {
             id: new_id(),
             title: create_rc_signal(String::new()),
+            description: create_rc_signal(String::new()),
         })
     };
     let poll_options_final: &ReadSignal<Vec<PollOption>> = create_memo(cx, || {
@@ -106,54 +157,84 @@ fn CreatePoll<G: Html>(cx: Scope) -> View<G> {
             .map(|o| PollOption {
                 id: PollOptionId::from_str(format!("{}", *o.id.get())),
                 title: o.title.get().to_string(),
-                description_text_markdown: "".to_string(),
+                description_text_markdown: o.description.get().to_string(),
             })
             .collect()
     });
     let submit_error = create_rc_signal(None);
     let submit_error_ref = create_ref(cx, submit_error.clone());
+    let submitting = create_rc_signal(false);
+    let submitting_ref = create_ref(cx, submitting.clone());
+    let api = use_context::<SharedClient>(cx).clone();
 
     let submit_poll = move |_| {
         let submit_error = submit_error.clone();
+        let submitting = submitting.clone();
+        let api = api.clone();
         if poll_title.get().is_empty() {
             submit_error
                 .modify()
                 .replace(format!("Poll title must not be empty"));
             return;
         }
+        if let Err(e) = score_scale.get().validate() {
+            submit_error
+                .modify()
+                .replace(format!("Invalid score scale: {e}"));
+            return;
+        }
         let poll_to_create = CreatePoll {
             title: poll_title.get().to_string(),
             description_text_markdown: poll_description.get().to_string(),
             options: (*poll_options_final.get()).clone(),
+            score_scale: *score_scale.get(),
+            tally_method: *tally_method.get(),
+            seats: *seats.get(),
+            proportional_method: *proportional_method.get(),
+            opens_at: *opens_at.get(),
+            closes_at: *closes_at.get(),
+            result_visibility: *result_visibility.get(),
+            anonymous: *anonymous.get(),
         };
         log::info!("creating poll {:#?}", poll_to_create);
+        submitting.set(true);
         wasm_bindgen_futures::spawn_local(async move {
-            let client = connect().await; // todo: connect only once
-            log::info!("connected");
-            let poll = client.create_poll(poll_to_create).await;
-            let poll = match poll {
+            let created = api.call(|c| c.create_poll(poll_to_create)).await;
+            submitting.set(false);
+            let created = match created {
                 Err(e) => {
                     submit_error.modify().replace(format!("Error: {}", e));
                     return;
                 }
                 Ok(p) => p,
             };
-            let poll = match poll {
-                Poll::V1(poll) => poll,
-            };
-
-            let id = poll.id.to_str();
-            navigate(&format!("/poll/{id}"));
+            let id = created.poll.poll.id.to_str();
+            let secret = created.admin_secret.to_str();
+            navigate(&format!("/poll/{id}/admin/{secret}"));
         });
     };
 
-    let poll_for_preview = create_memo(cx, || PollV1 {
-        id: PublicPollId::from_str("preview".to_string()),
-        title: poll_title.get().to_string(),
-        description_text_markdown: poll_description.get().to_string(),
-        options: (*poll_options_final.get()).clone(),
-        votes: vec![],
-        result: None,
+    let poll_for_preview = create_memo(cx, || PollView {
+        poll: PollV2 {
+            id: PublicPollId::from_str("preview".to_string()),
+            title: poll_title.get().to_string(),
+            description_text_markdown: poll_description.get().to_string(),
+            options: (*poll_options_final.get()).clone(),
+            score_scale: *score_scale.get(),
+            votes: vec![],
+            tally_method: *tally_method.get(),
+            seats: *seats.get(),
+            proportional_method: *proportional_method.get(),
+            result: None,
+            vote_history: vec![],
+            closed: false,
+            opens_at: *opens_at.get(),
+            closes_at: *closes_at.get(),
+            result_visibility: *result_visibility.get(),
+            anonymous: *anonymous.get(),
+        },
+        results_hidden: false,
+        aggregates: None,
     });
     /*create_effect(cx, || {
         log::info!("{:#?}", poll_for_preview.get());
@@ -175,6 +256,100 @@ fn CreatePoll<G: Html>(cx: Scope) -> View<G> {
                         textarea(class="textarea", bind:value=poll_description)
                     }
                 }
+                div(class="field is-grouped") {
+                    div(class="control") {
+                        label(class="label") { "Lowest score" }
+                        input(class="input", type="number", bind:value=scale_min)
+                    }
+                    div(class="control") {
+                        label(class="label") { "Highest score" }
+                        input(class="input", type="number", bind:value=scale_max)
+                    }
+                    div(class="control") {
+                        label(class="label") { "Step" }
+                        input(class="input", type="number", min="0", bind:value=scale_step)
+                    }
+                }
+                div(class="field") {
+                    label(class="checkbox") {
+                        input(type="checkbox", bind:checked=allow_abstain)
+                        " Voters may abstain on an option"
+                    }
+                }
+                div(class="field") {
+                    label(class="checkbox") {
+                        input(type="checkbox", bind:checked=anonymous)
+                        " Secret ballot: only publish the number of votes per score, not who voted what"
+                    }
+                }
+                div(class="field") {
+                    label(class="label") { "Tally method" }
+                    div(class="control") {
+                        div(class="select") {
+                            select(bind:value=tally_method_name) {
+                                option(value="mean") { "Mean" }
+                                option(value="sum") { "Sum" }
+                                option(value="median") { "Median" }
+                                option(value="trimmed_mean") { "Trimmed mean" }
+                                option(value="star") { "STAR (score then automatic runoff)" }
+                                option(value="majority_judgment") { "Majority judgment (median grade)" }
+                            }
+                        }
+                    }
+                }
+                (if *tally_method_name.get() == "trimmed_mean" {
+                    view! { cx,
+                        div(class="field") {
+                            label(class="label") { "Percentage of lowest and highest scores to drop" }
+                            div(class="control") {
+                                input(class="input", type="number", min="0", max="49", bind:value=trim_percent)
+                            }
+                        }
+                    }
+                } else {view! {cx, ""}})
+                div(class="field is-grouped") {
+                    div(class="control") {
+                        label(class="label") { "Voting opens (optional)" }
+                        input(class="input", type="datetime-local", bind:value=opens_at_text)
+                    }
+                    div(class="control") {
+                        label(class="label") { "Voting closes (optional)" }
+                        input(class="input", type="datetime-local", bind:value=closes_at_text)
+                    }
+                }
+                div(class="field") {
+                    label(class="label") { "Who can see the results" }
+                    div(class="control") {
+                        div(class="select") {
+                            select(bind:value=result_visibility_name) {
+                                option(value="always") { "Everyone, at any time" }
+                                option(value="after_voting") { "Only people who voted, until the poll closes" }
+                                option(value="after_closing") { "Nobody until the poll closes" }
+                            }
+                        }
+                    }
+                }
+                div(class="field") {
+                    label(class="label") { "Number of winners" }
+                    div(class="control") {
+                        input(class="input", type="number", min="1", bind:value=seats_text)
+                    }
+                }
+                (if *seats.get() > 1 {
+                    view! { cx,
+                        div(class="field") {
+                            label(class="label") { "Proportional method" }
+                            div(class="control") {
+                                div(class="select") {
+                                    select(bind:value=proportional_method_name) {
+                                        option(value="rrv") { "Reweighted range voting" }
+                                        option(value="sss") { "Sequentially spent score" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                } else {view! {cx, ""}})
             }
             "Options:"
             ol {
@@ -189,7 +364,7 @@ fn CreatePoll<G: Html>(cx: Scope) -> View<G> {
             button(class="button is-secondary", on:click=add_option) {
                 "Add option"
             }
-            button(class="button is-primary", on:click=submit_poll) {
+            button(class=button_class("button is-primary", *submitting_ref.get()), on:click=submit_poll) {
                 "Submit Poll"
             }
             (if let Some(e) = (*submit_error_ref.get()).clone() {
@@ -214,56 +389,245 @@ fn CreatePoll<G: Html>(cx: Scope) -> View<G> {
 }
 
 #[component]
-fn ChangingViewPoll<'a, G: Html>(cx: Scope<'a>, poll: &'a ReadSignal<PollV1>) -> View<G> {
-    sycamore::view::View::new_dyn(cx, move || ViewPoll(cx, (*poll.get()).clone()))
+fn ChangingViewPoll<'a, G: Html>(cx: Scope<'a>, poll: &'a ReadSignal<PollView>) -> View<G> {
+    sycamore::view::View::new_dyn(cx, move || {
+        ViewPoll(cx, create_signal(cx, (*poll.get()).clone()))
+    })
+}
+
+/// the poll shown on its own page, kept up to date by a subscription for `voter`
+#[derive(Clone)]
+struct LivePoll {
+    poll: RcSignal<PollView>,
+    /// changes once the visitor votes, since results hidden until voting are only sent to
+    /// subscriptions of voters
+    voter: RcSignal<Option<VoterIdentity>>,
 }
 
 #[component]
 async fn LoadViewPoll<G: Html>(cx: Scope<'_>, _poll_id: String) -> View<G> {
     let poll_id = PublicPollId::from_str(_poll_id.to_string());
-    let poll = connect().await.get_poll(poll_id).await;
+    let voter = stored_voter_identity();
+    let poll = use_context::<SharedClient>(cx)
+        .call_idempotent(|c| c.get_poll(poll_id.clone(), voter.clone()))
+        .await;
     match poll {
         Ok(poll) => {
-            let poll = match poll {
-                Poll::V1(p) => p,
+            let poll_id = poll.poll.id.clone();
+            let pairwise_link = format!("/poll/{}/pairwise", poll_id.to_str());
+            let live = LivePoll {
+                poll: create_rc_signal(poll),
+                voter: create_rc_signal(voter),
             };
+            provide_context(cx, live.clone());
+            let ws_url = api_client::endpoints().await.ws_url.clone();
+            let subscription = create_rc_signal(None);
+            create_effect(cx, {
+                let live = live.clone();
+                let subscription = subscription.clone();
+                move || {
+                    let voter = (*live.voter.get()).clone();
+                    let live_poll = live.poll.clone();
+                    let subscribed = live_updates::subscribe_poll(
+                        &ws_url,
+                        poll_id.clone(),
+                        voter,
+                        move |poll| live_poll.set(poll),
+                    );
+                    match subscribed {
+                        // dropping the previous subscription unsubscribes it
+                        Ok(s) => subscription.set(Some(s)),
+                        Err(e) => log::warn!("live updates are not available: {:?}", e),
+                    }
+                }
+            });
+            on_cleanup(cx, move || subscription.set(None));
+            let live_poll = create_ref(cx, live.poll);
             view! { cx,
-                ViewPoll(poll)
+                ViewPoll(live_poll)
                 a(class="button is-info", href="/") { "Create a new poll" }
+                a(class="button", href=pairwise_link) { "Head-to-head comparison" }
             }
         }
-        Err(RpcError::JsonRpcError(jsonrpc_core::types::error::Error {
-            code: jsonrpc_core::ErrorCode::ServerError(i),
-            message,
-            data: _,
-        })) => {
-            view! { cx,
+        Err(e) => match e {
+            ClientError::Api(ApiError::PollNotFound) => view! { cx,
                 div(class="notification is-danger") {
-                    "Could not load poll: "(message)" (E"(i)")"
+                    "There is no poll with the id "(_poll_id)"."
+                }
+            },
+            ClientError::Api(e) => {
+                let code = e.code();
+                view! { cx,
+                    div(class="notification is-danger") {
+                        "Could not load poll: "(e)" (E"(code)")"
+                    }
                 }
             }
-        }
-        Err(e) => {
-            view! { cx,
+            e => view! { cx,
                 div(class="notification is-danger") {
                     "Could not load poll "(_poll_id)": " (e)
                 }
+            },
+        },
+    }
+}
+
+#[component]
+async fn LoadViewPairwise<G: Html>(cx: Scope<'_>, _poll_id: String) -> View<G> {
+    let poll_id = PublicPollId::from_str(_poll_id.to_string());
+    let voter = stored_voter_identity();
+    let api = use_context::<SharedClient>(cx);
+    let result = match api
+        .call_idempotent(|c| c.get_poll(poll_id.clone(), voter.clone()))
+        .await
+    {
+        Ok(view) => api
+            .call_idempotent(|c| c.get_pairwise(poll_id.clone(), voter.clone()))
+            .await
+            .map(|pairwise| (view.poll, pairwise)),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok((poll, pairwise)) => {
+            let back_link = format!("/poll/{}", poll.id.to_str());
+            view! { cx,
+                h2(class="title is-2") { (poll.title) }
+                ViewPairwise { options: poll.options, pairwise }
+                a(class="button is-info", href=back_link) { "Back to the poll" }
+            }
+        }
+        Err(e) => match e {
+            ClientError::Api(ApiError::ResultsHidden) => view! { cx,
+                div(class="notification is-warning") {
+                    "The results of this poll are hidden for now."
+                }
+            },
+            e => view! { cx,
+                div(class="notification is-danger") {
+                    "Could not load head-to-head comparison of poll "(_poll_id)": " (e)
+                }
+            },
+        },
+    }
+}
+
+#[derive(Prop)]
+struct PairwiseProps {
+    options: Vec<PollOption>,
+    pairwise: PairwiseResult,
+}
+#[component]
+fn ViewPairwise<G: Html>(cx: Scope, props: PairwiseProps) -> View<G> {
+    let title_of = |id: &PollOptionId| {
+        props
+            .options
+            .iter()
+            .find(|o| &o.id == id)
+            .map(|o| o.title.clone())
+            .unwrap_or_default()
+    };
+    let titles: Vec<String> = props.pairwise.options.iter().map(title_of).collect();
+    let header = View::new_fragment(
+        titles
+            .iter()
+            .map(|t| {
+                let t = t.clone();
+                view! { cx, td { (t) } }
+            })
+            .collect(),
+    );
+    let matrix = &props.pairwise.matrix;
+    let rows = View::new_fragment(
+        titles
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let t = t.clone();
+                let cells = View::new_fragment(
+                    (0..titles.len())
+                        .map(|j| {
+                            if i == j {
+                                return view! { cx, td { "–" } };
+                            }
+                            let wins = matrix[i][j];
+                            let cls = if wins > matrix[j][i] {
+                                "has-text-success"
+                            } else if wins < matrix[j][i] {
+                                "has-text-danger"
+                            } else {
+                                ""
+                            };
+                            view! { cx, td(class=cls) { (wins) } }
+                        })
+                        .collect(),
+                );
+                view! { cx,
+                    tr {
+                        td { (t) }
+                        (cells)
+                    }
+                }
+            })
+            .collect(),
+    );
+    let verdict = match (
+        &props.pairwise.condorcet_winner,
+        &props.pairwise.schulze_ranking,
+    ) {
+        (Some(winner), _) => format!(
+            "Condorcet winner: {} beats every other option head-to-head.",
+            title_of(winner)
+        ),
+        (None, Some(ranking)) => format!(
+            "There is no Condorcet winner. Schulze ranking: {}",
+            ranking
+                .iter()
+                .map(|group| group.iter().map(title_of).collect::<Vec<_>>().join(" = "))
+                .collect::<Vec<_>>()
+                .join(" > ")
+        ),
+        (None, None) => "There is no Condorcet winner.".to_string(),
+    };
+    view! { cx,
+        div {
+            h4(class="title is-4") { "Head-to-head comparison" }
+            p { "Each cell counts the ballots that scored the row option higher than the column option." }
+            table(class="table") {
+                thead {
+                    tr {
+                        td { "" }
+                        (header)
+                    }
+                }
+                tbody {
+                    (rows)
+                }
             }
+            div(class="notification is-info") { (verdict) }
         }
     }
 }
 
 #[derive(Prop)]
 struct VPOProps {
-    votes: RcSignal<HashMap<PollOptionId, Option<i32>>>,
+    votes: RcSignal<HashMap<PollOptionId, Option<f64>>>,
     option: PollOptionId,
+    scale: ScoreScale,
+    disabled: RcSignal<bool>,
 }
 #[component]
 fn VotePollOption<'a, G: Html>(cx: Scope<'a>, props: VPOProps) -> View<G> {
+    let disabled = create_ref(cx, props.disabled.clone());
+    let abstain = if props.scale.allow_abstain {
+        vec![None]
+    } else {
+        vec![]
+    };
     let op = View::new_fragment(
-        (-1..=9)
+        abstain
+            .into_iter()
+            .chain(props.scale.values().into_iter().map(Some))
             .map(|e| {
-                let e = if e >= 0 { Some(e) } else { None };
                 let v = props.votes.clone();
                 let vref = create_ref(cx, v);
                 let o = props.option.clone();
@@ -278,7 +642,7 @@ fn VotePollOption<'a, G: Html>(cx: Scope<'a>, props: VPOProps) -> View<G> {
                     })
                 };
                 view! { cx,
-                    button(class=cls, on:click=move |_| {
+                    button(class=cls, disabled=*disabled.get(), on:click=move |_| {
                         log::debug!("set vote: option={:?}, value={:?}", o, e);
                         let mut x = vref.modify();
                         x.insert(o.clone(), e);
@@ -294,9 +658,34 @@ fn VotePollOption<'a, G: Html>(cx: Scope<'a>, props: VPOProps) -> View<G> {
     }
 }
 #[component]
-fn ViewPoll<'a, G: Html>(cx: Scope<'a>, poll: PollV1) -> View<G> {
+/// the parts of the poll that change when ballots are cast, i.e. the results, follow `live_poll`
+fn ViewPoll<'a, G: Html>(cx: Scope<'a>, live_poll: &'a ReadSignal<PollView>) -> View<G> {
+    let poll = live_poll.get().poll.clone();
     let user_name = create_signal(cx, String::new());
-    let my_votes: RcSignal<HashMap<PollOptionId, Option<i32>>> = create_rc_signal(HashMap::new());
+    let my_votes: RcSignal<HashMap<PollOptionId, Option<f64>>> = create_rc_signal(HashMap::new());
+    let scale = poll.score_scale;
+    let now = create_rc_signal(now_secs());
+    if poll.opens_at.is_some() || poll.closes_at.is_some() {
+        let now = now.clone();
+        let interval = gloo_timers::callback::Interval::new(1000, move || now.set(now_secs()));
+        on_cleanup(cx, move || drop(interval));
+    }
+    // follows the live poll, which changes when an admin closes or reopens it
+    let voting_closed = create_rc_signal(is_voting_closed(&poll, *now.get()));
+    create_effect(cx, {
+        let now = now.clone();
+        let voting_closed = voting_closed.clone();
+        move || {
+            let closed = is_voting_closed(&live_poll.get().poll, *now.get());
+            if *voting_closed.get_untracked() != closed {
+                voting_closed.set(closed);
+            }
+        }
+    });
+    let voting_closed_ref = create_ref(cx, voting_closed.clone());
+    let countdown_ref = create_memo(cx, move || {
+        countdown_text(&live_poll.get().poll, *now.get())
+    });
 
     let options = View::new_fragment(
         poll.options
@@ -304,12 +693,16 @@ fn ViewPoll<'a, G: Html>(cx: Scope<'a>, poll: PollV1) -> View<G> {
             .into_iter()
             .map(|o| {
                 let votes = my_votes.clone();
+                let disabled = voting_closed.clone();
 
                 view! { cx,
                     tr {
-                        td { (o.title) }
                         td {
-                            VotePollOption { votes, option: o.id.clone() }
+                            strong { (o.title) }
+                            Markdown(o.description_text_markdown)
+                        }
+                        td {
+                            VotePollOption { votes, option: o.id.clone(), scale, disabled }
                         }
                     }
                 }
@@ -317,52 +710,143 @@ fn ViewPoll<'a, G: Html>(cx: Scope<'a>, poll: PollV1) -> View<G> {
             .collect::<Vec<View<G>>>(),
     );
     let submit_error = create_rc_signal(None);
+    let submit_error_ref = create_ref(cx, submit_error.clone());
+    let submitting = create_rc_signal(false);
+    let submitting_ref = create_ref(cx, submitting.clone());
+    let api = create_ref(cx, use_context::<SharedClient>(cx).clone());
+    // not provided in the preview of the create page
+    let live = create_ref(cx, try_use_context::<LivePoll>(cx).cloned());
     let poll_id = poll.id.clone();
+    let poll_options = poll.options.clone();
     let submit_vote = move |_| {
         let submit_error = submit_error.clone();
+        let submitting = submitting.clone();
+        let api = api.clone();
+        let live = live.clone();
         let poll_id = poll_id.clone();
+        let poll_options = poll_options.clone();
         log::debug!("submitting vote");
-        let vote = ScoreVote {
-            user_id: PublicUserId::from_str("123".to_string()),
-            user_name: user_name.get().to_string(),
-            votes: my_votes
-                .get()
-                .iter()
-                .map(|(k, v)| (k.clone(), v.map(|e| e as f64)))
-                .collect(),
-        };
+        let user_name = user_name.get().to_string();
+        let votes = (*my_votes.get()).clone();
+        submitting.set(true);
         wasm_bindgen_futures::spawn_local(async move {
-            let client = connect().await; // todo: connect only once
-            let poll = client.vote(poll_id, vote).await;
-            let poll = match poll {
+            let identity = api.call(|c| async move { voter_identity(&c).await }).await;
+            let identity = match identity {
+                Ok(identity) => identity,
                 Err(e) => {
-                    submit_error.modify().replace(format!("Error: {}", e));
+                    submit_error
+                        .modify()
+                        .replace(format!("Could not register as a voter: {}", e));
+                    submitting.set(false);
                     return;
                 }
-                Ok(p) => p,
             };
+            let vote = ScoreVote {
+                user_id: identity.user_id.clone(),
+                user_name,
+                votes,
+            };
+            let poll = api
+                .call(|c| c.vote(poll_id, vote, identity.token.clone()))
+                .await;
+            submitting.set(false);
             let poll = match poll {
-                Poll::V1(poll) => poll,
+                Err(e) => {
+                    submit_error
+                        .modify()
+                        .replace(vote_error_message(&poll_options, e));
+                    return;
+                }
+                Ok(p) => p,
             };
+            let id = poll.poll.id.to_str().to_string();
+            if let Some(live) = live {
+                let subscribed_voter = (*live.voter.get()).as_ref().map(|v| v.user_id.clone());
+                if subscribed_voter.as_ref() != Some(&identity.user_id) {
+                    live.voter.set(Some(identity));
+                }
+                live.poll.set(poll);
+            }
 
-            let id = poll.id.to_str();
             navigate(&format!("/poll/{id}"));
         });
     };
+    let retract_error = submit_error_ref.clone();
+    let retracting = create_rc_signal(false);
+    let retracting_ref = create_ref(cx, retracting.clone());
+    let poll_id = poll.id.clone();
+    let poll_options = poll.options.clone();
+    let retract_vote = move |_| {
+        let submit_error = retract_error.clone();
+        let retracting = retracting.clone();
+        let api = api.clone();
+        let poll_id = poll_id.clone();
+        let poll_options = poll_options.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let identity = match stored_voter_identity() {
+                Some(identity) => identity,
+                None => {
+                    submit_error.modify().replace(describe_ballot_error(
+                        &poll_options,
+                        &BallotError::NoExistingBallot,
+                    ));
+                    return;
+                }
+            };
+            retracting.set(true);
+            let retracted = api
+                .call(|c| c.retract_vote(poll_id.clone(), identity.user_id, identity.token))
+                .await;
+            retracting.set(false);
+            match retracted {
+                Err(e) => {
+                    submit_error
+                        .modify()
+                        .replace(vote_error_message(&poll_options, e));
+                }
+                Ok(_) => navigate(&format!("/poll/{}", poll_id.to_str())),
+            }
+        });
+    };
 
-    let poll_clone = poll.clone();
+    let results = View::new_dyn(cx, move || {
+        let poll = (*live_poll.get()).clone();
+        let votes = match &poll.aggregates {
+            Some(aggregates) => aggregates.ballots,
+            None => poll.poll.votes.len(),
+        };
+        if poll.results_hidden {
+            let message = match poll.poll.result_visibility {
+                ResultVisibility::AfterVoting => {
+                    "The results are hidden until you vote or the poll closes."
+                }
+                _ => "The results are hidden until the poll closes.",
+            };
+            view! { cx, div(class="notification is-warning") { (message) } }
+        } else {
+            view! { cx,
+                ViewPollResult(poll)
+                (votes) " votes so far"
+            }
+        }
+    });
+    let anonymous = poll.anonymous;
     let poll_title = poll.title.clone();
     view! { cx,
         div(class="poll") {
             h2(class="title is-2") {(poll_title)}
-            div(class="subtitle is-3") {(poll.description_text_markdown)}
-            ViewPollResult(poll_clone)
-            (poll.votes.len()) " votes so far"
+            div(class="subtitle is-3") { Markdown(poll.description_text_markdown.clone()) }
+            (results)
+            (if let Some(text) = (*countdown_ref.get()).clone() {
+                view! { cx, div(class="notification is-info") { (text) } }
+            } else {view! {cx, ""}})
             div {
                 "Vote on " i { (poll_title) }
-                div {
-                    "Your name: " input(bind:value=user_name) {}
-                }
+                (if anonymous {
+                    view! { cx, "" }
+                } else {
+                    view! { cx, div { "Your name: " input(bind:value=user_name) {} } }
+                })
                 table(class="table") {
                     thead {
                         tr { td { "Option" } td { "Your vote" } }
@@ -371,20 +855,462 @@ fn ViewPoll<'a, G: Html>(cx: Scope<'a>, poll: PollV1) -> View<G> {
                         (options)
                     }
                 }
-                button(class="button is-primary", on:click=submit_vote) { "Submit vote" }
+                button(class=button_class("button is-primary", *submitting_ref.get()), disabled=*voting_closed_ref.get(), on:click=submit_vote) { "Submit vote" }
+                button(class=button_class("button is-light", *retracting_ref.get()), disabled=*voting_closed_ref.get(), on:click=retract_vote) { "Retract my vote" }
+                (if let Some(e) = (*submit_error_ref.get()).clone() {
+                    view! { cx,
+                        div(class="notification is-warning") {"Could not submit vote: " (e)} }
+                } else {view! {cx, ""}})
             }
         }
     }
 }
 
+/// current unix timestamp in seconds
+fn now_secs() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+/// parses the value of a `datetime-local` input (in local time) to a unix timestamp in seconds
+fn parse_datetime_local(value: &str) -> Option<u64> {
+    if value.is_empty() {
+        return None;
+    }
+    let ms = js_sys::Date::new(&value.into()).get_time();
+    if ms.is_nan() {
+        return None;
+    }
+    Some((ms / 1000.0) as u64)
+}
+
+/// whether the poll doesn't accept ballots at the unix timestamp `now`
+fn is_voting_closed(poll: &PollV2, now: u64) -> bool {
+    poll.closed
+        || poll.opens_at.is_some_and(|t| now < t)
+        || poll.closes_at.is_some_and(|t| now >= t)
+}
+
+fn format_duration(secs: u64) -> String {
+    let (days, hours, minutes, seconds) = (
+        secs / 86400,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+    );
+    if days > 0 {
+        format!("{days}d {hours}h {minutes}m {seconds}s")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m {seconds}s")
+    } else {
+        format!("{minutes}m {seconds}s")
+    }
+}
+
+/// tells the voter when voting starts or ends
+fn countdown_text(poll: &PollV2, now: u64) -> Option<String> {
+    if poll.closed {
+        return Some("This poll is closed.".to_string());
+    }
+    match (poll.opens_at, poll.closes_at) {
+        (Some(opens_at), _) if now < opens_at => Some(format!(
+            "Voting opens in {}.",
+            format_duration(opens_at - now)
+        )),
+        (_, Some(closes_at)) if now < closes_at => Some(format!(
+            "Voting closes in {}.",
+            format_duration(closes_at - now)
+        )),
+        (_, Some(_)) => Some("Voting has ended.".to_string()),
+        _ => None,
+    }
+}
+
+#[derive(Prop)]
+struct AdminRouteProps {
+    poll_id: String,
+    secret: String,
+}
+#[component]
+async fn LoadAdminPoll<G: Html>(cx: Scope<'_>, props: AdminRouteProps) -> View<G> {
+    let poll_id = PublicPollId::from_str(props.poll_id.to_string());
+    let secret = AdminSecret::new(props.secret.to_string());
+    let poll = use_context::<SharedClient>(cx)
+        .call_idempotent(|c| c.get_poll_admin(poll_id.clone(), secret.clone()))
+        .await;
+    match poll {
+        Ok(poll) => view! { cx,
+            AdminPoll { poll: poll.poll, secret }
+        },
+        Err(e) => view! { cx,
+            div(class="notification is-danger") {
+                "Could not load poll "(props.poll_id)" for administration: " (e)
+            }
+        },
+    }
+}
+
+/// what the admin page needs to run an action
+#[derive(Clone)]
+struct AdminContext {
+    api: SharedClient,
+    poll: RcSignal<PollV2>,
+    error: RcSignal<Option<String>>,
+    /// an action is running
+    busy: RcSignal<bool>,
+}
+
+/// runs an admin RPC and shows the returned poll, or the error
+fn run_admin_action<F, Fut>(admin: &AdminContext, action: F)
+where
+    F: FnOnce(common::ApiClient) -> Fut + 'static,
+    Fut: std::future::Future<Output = Result<PollView, ClientError>>,
+{
+    let AdminContext {
+        api,
+        poll,
+        error,
+        busy,
+    } = admin.clone();
+    busy.set(true);
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = api.call(action).await;
+        busy.set(false);
+        match result {
+            Ok(view) => {
+                error.set(None);
+                poll.set(view.poll);
+            }
+            Err(e) => error.set(Some(match e {
+                ClientError::Api(ApiError::Unauthorized) => {
+                    "The admin link is not valid for this poll.".to_string()
+                }
+                e => format!("Error: {}", e),
+            })),
+        }
+    });
+}
+
+#[derive(Prop)]
+struct AdminPollProps {
+    poll: PollV2,
+    secret: AdminSecret,
+}
 #[component]
-fn ViewPollResult<'a, G: Html>(cx: Scope<'a>, poll: PollV1) -> View<G> {
+fn AdminPoll<G: Html>(cx: Scope, props: AdminPollProps) -> View<G> {
+    let poll_id = props.poll.id.clone();
+    let public_link = format!("/poll/{}", poll_id.to_str());
+    let title = create_signal(cx, props.poll.title.clone());
+    let description = create_signal(cx, props.poll.description_text_markdown.clone());
+    let new_option = create_signal(cx, String::new());
+    let poll = create_rc_signal(props.poll);
+    let poll_ref = create_ref(cx, poll.clone());
+    let error = create_rc_signal(None::<String>);
+    let error_ref = create_ref(cx, error.clone());
+    let busy = create_rc_signal(false);
+    let busy_ref = create_ref(cx, busy.clone());
+    let admin = create_ref(
+        cx,
+        AdminContext {
+            api: use_context::<SharedClient>(cx).clone(),
+            poll,
+            error,
+            busy,
+        },
+    );
+    let secret = create_ref(cx, props.secret);
+    let poll_id = create_ref(cx, poll_id);
+
+    let toggle_closed = move |_| {
+        let (id, secret) = (poll_id.clone(), secret.clone());
+        if poll_ref.get().closed {
+            run_admin_action(admin, |c| async move { c.reopen_poll(id, secret).await });
+        } else {
+            run_admin_action(admin, |c| async move { c.close_poll(id, secret).await });
+        }
+    };
+    let save_text = move |_| {
+        let (id, secret) = (poll_id.clone(), secret.clone());
+        let edit = EditPoll {
+            title: Some(title.get().to_string()),
+            description_text_markdown: Some(description.get().to_string()),
+            add_options: vec![],
+        };
+        run_admin_action(
+            admin,
+            |c| async move { c.edit_poll(id, secret, edit).await },
+        );
+    };
+    let add_option = move |_| {
+        if new_option.get().is_empty() {
+            return;
+        }
+        let (id, secret) = (poll_id.clone(), secret.clone());
+        // option ids only need to be unique within the poll
+        let option_id = (poll_ref.get().options.len() + 1..)
+            .map(|i| PollOptionId::from_str(format!("{i}")))
+            .find(|id| !poll_ref.get().options.iter().any(|o| &o.id == id))
+            .expect("infinite iterator");
+        let edit = EditPoll {
+            title: None,
+            description_text_markdown: None,
+            add_options: vec![PollOption {
+                id: option_id,
+                title: new_option.get().to_string(),
+                description_text_markdown: "".to_string(),
+            }],
+        };
+        new_option.set(String::new());
+        run_admin_action(
+            admin,
+            |c| async move { c.edit_poll(id, secret, edit).await },
+        );
+    };
+    let delete_poll = move |_| {
+        let confirmed = web_sys::window()
+            .and_then(|w| {
+                w.confirm_with_message("Delete this poll and all ballots?")
+                    .ok()
+            })
+            .unwrap_or(false);
+        if !confirmed {
+            return;
+        }
+        let (id, secret) = (poll_id.clone(), secret.clone());
+        let AdminContext {
+            api, error, busy, ..
+        } = admin.clone();
+        busy.set(true);
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = api.call(|c| c.delete_poll(id, secret)).await;
+            busy.set(false);
+            match result {
+                Ok(()) => navigate("/"),
+                Err(e) => error.set(Some(format!("Error: {}", e))),
+            }
+        });
+    };
+
+    let status = create_memo(cx, || {
+        if poll_ref.get().closed {
+            ("Closed", "Reopen poll")
+        } else {
+            ("Open", "Close poll")
+        }
+    });
+    let votes = create_memo(cx, || poll_ref.get().votes.clone());
+    let history = create_memo(cx, || poll_ref.get().vote_history.clone());
+
+    view! { cx,
+        div {
+            h2(class="title is-2") { "Manage poll" }
+            div(class="notification is-warning") {
+                "This is the admin page of your poll. Keep the link to this page secret: "
+                "everyone who has it can edit, close and delete the poll. "
+                "Share the " a(href=public_link) { "public poll page" } " with voters instead."
+            }
+            (if let Some(e) = (*error_ref.get()).clone() {
+                view! { cx, div(class="notification is-danger") { (e) } }
+            } else {view! {cx, ""}})
+            div(class="field") {
+                "Status: " strong { (status.get().0) } " "
+                button(class=button_class("button", *busy_ref.get()), on:click=toggle_closed) { (status.get().1) }
+            }
+            div(class="field") {
+                label(class="label") { "Poll title" }
+                div(class="control") {
+                    input(class="input", bind:value=title)
+                }
+            }
+            div(class="field") {
+                label(class="label") { "Poll description (markdown)" }
+                div(class="control") {
+                    textarea(class="textarea", bind:value=description)
+                }
+            }
+            div(class="box") {
+                (view! { cx, Markdown(description.get().to_string()) })
+            }
+            button(class=button_class("button is-primary", *busy_ref.get()), on:click=save_text) { "Save title and description" }
+            h4(class="title is-4") { "Options" }
+            ol {
+                Indexed {
+                    iterable: create_memo(cx, || poll_ref.get().options.clone()),
+                    view: |cx, o| view! { cx,
+                        li {
+                            (o.title)
+                            Markdown(o.description_text_markdown)
+                        }
+                    },
+                }
+            }
+            div(class="field has-addons") {
+                div(class="control") {
+                    input(class="input", bind:value=new_option, placeholder="New option")
+                }
+                div(class="control") {
+                    button(class=button_class("button", *busy_ref.get()), on:click=add_option) { "Add option" }
+                }
+            }
+            h4(class="title is-4") { "Ballots" }
+            (if poll_ref.get().anonymous {
+                view! { cx, p { "This poll uses secret ballots, so individual ballots can't be shown or removed." } }
+            } else {view! {cx, ""}})
+            ul {
+                Indexed {
+                    iterable: votes,
+                    view: move |cx, v| {
+                        let user_id = v.user_id.clone();
+                        let remove = move |_| {
+                            let (id, secret, user_id) = (poll_id.clone(), secret.clone(), user_id.clone());
+                            run_admin_action(admin, |c| async move {
+                                c.remove_vote(id, secret, user_id).await
+                            });
+                        };
+                        view! { cx,
+                            li {
+                                (v.user_name) " "
+                                button(class="button is-small is-warning", on:click=remove) { "Remove" }
+                            }
+                        }
+                    },
+                }
+            }
+            (ViewVoteHistory(cx, (*history.get()).clone()))
+            button(class=button_class("button is-danger", *busy_ref.get()), on:click=delete_poll) { "Delete poll" }
+        }
+    }
+}
+
+const VOTER_IDENTITY_KEY: &str = "voter_identity";
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// the voter identity of this browser, if it has been issued already
+fn stored_voter_identity() -> Option<VoterIdentity> {
+    let json = local_storage()?.get_item(VOTER_IDENTITY_KEY).ok()??;
+    serde_json::from_str(&json).ok()
+}
+
+/// loads the voter identity from localStorage, asking the server for a new one on first use
+async fn voter_identity(client: &common::ApiClient) -> Result<VoterIdentity, ClientError> {
+    if let Some(identity) = stored_voter_identity() {
+        return Ok(identity);
+    }
+    let identity = client.create_voter().await?;
+    match (local_storage(), serde_json::to_string(&identity)) {
+        (Some(storage), Ok(json)) => {
+            if storage.set_item(VOTER_IDENTITY_KEY, &json).is_err() {
+                log::warn!("could not persist voter identity");
+            }
+        }
+        _ => log::warn!("could not persist voter identity"),
+    }
+    Ok(identity)
+}
+
+/// bulma classes of a button that shows a spinner while its request runs
+fn button_class(class: &str, loading: bool) -> String {
+    if loading {
+        format!("{class} is-loading")
+    } else {
+        class.to_string()
+    }
+}
+
+/// user facing message for a failed vote or retraction
+fn vote_error_message(options: &[PollOption], e: ClientError) -> String {
+    match e {
+        ClientError::Api(ApiError::InvalidBallot(ballot_error)) => {
+            describe_ballot_error(options, &ballot_error)
+        }
+        ClientError::Api(ApiError::PollClosed) => "This poll is closed.".to_string(),
+        ClientError::Api(ApiError::OutsideVotingPeriod { .. }) => {
+            "This poll does not accept votes right now.".to_string()
+        }
+        e => format!("Error: {}", e),
+    }
+}
+
+#[component]
+fn ViewVoteHistory<G: Html>(cx: Scope, history: Vec<SupersededVote>) -> View<G> {
+    if history.is_empty() {
+        return view! { cx, "" };
+    }
+    let entries = View::new_fragment(
+        history
+            .into_iter()
+            .map(|h| {
+                let what = match h.reason {
+                    SupersededReason::Replaced => "changed their vote",
+                    SupersededReason::Retracted => "retracted their vote",
+                    SupersededReason::RemovedByAdmin => "had their vote removed by the admin",
+                };
+                let when: String = js_sys::Date::new(&((h.superseded_at as f64) * 1000.0).into())
+                    .to_locale_string("default", &js_sys::Object::new())
+                    .into();
+                view! { cx, li { (h.vote.user_name) " " (what) " (" (when) ")" } }
+            })
+            .collect(),
+    );
+    view! { cx,
+        div {
+            h5(class="title is-5") { "Changed votes" }
+            ul { (entries) }
+        }
+    }
+}
+
+/// user facing explanation of why the server rejected a ballot
+fn describe_ballot_error(options: &[PollOption], e: &BallotError) -> String {
+    let title_of = |id: &PollOptionId| {
+        options
+            .iter()
+            .find(|o| &o.id == id)
+            .map(|o| format!("\"{}\"", o.title))
+            .unwrap_or_else(|| "an unknown option".to_string())
+    };
+    match e {
+        BallotError::EmptyUserName => "Please enter your name.".to_string(),
+        BallotError::EmptyBallot => "Please score at least one option.".to_string(),
+        BallotError::UnknownOption { .. } => {
+            "The poll has changed since you loaded it, please reload the page.".to_string()
+        }
+        BallotError::NonFiniteScore { option } => {
+            format!("The score for {} is not a number.", title_of(option))
+        }
+        BallotError::ScoreOutOfScale { option, score } => {
+            format!(
+                "The score {score} for {} is out of range.",
+                title_of(option)
+            )
+        }
+        BallotError::ScoreNotOnStep { option, score } => format!(
+            "The score {score} for {} is not one of the allowed scores.",
+            title_of(option)
+        ),
+        BallotError::MissingScore { option } => {
+            format!("Please give {} a score.", title_of(option))
+        }
+        BallotError::NoExistingBallot => "You have not voted in this poll yet.".to_string(),
+        BallotError::InvalidVoterToken => {
+            "Your voter identity was not accepted by the server.".to_string()
+        }
+    }
+}
+
+#[component]
+fn ViewPollResult<'a, G: Html>(cx: Scope<'a>, poll: PollView) -> View<G> {
+    let PollView {
+        poll, aggregates, ..
+    } = poll;
     let mut votes = poll.votes.clone();
-    if let Some(v) = poll.result {
+    if let Some(r) = &poll.result {
         votes.push(ScoreVote {
             user_id: PublicUserId::from_str("fake"),
-            user_name: format!("Result"),
-            votes: v,
+            user_name: format!("Result ({})", r.method.name()),
+            votes: r.scores.clone(),
         });
     };
     let options = View::new_fragment(
@@ -406,42 +1332,536 @@ fn ViewPollResult<'a, G: Html>(cx: Scope<'a>, poll: PollV1) -> View<G> {
             .collect::<Vec<View<G>>>(),
     );
     let vref = create_ref(cx, votes);
+    let ballots = match aggregates {
+        Some(aggregates) => {
+            let result = poll
+                .result
+                .as_ref()
+                .map(|r| (r.method.name(), r.scores.clone()));
+            view! { cx,
+                ViewAggregates { options: poll.options.clone(), scale: poll.score_scale, aggregates, result }
+            }
+        }
+        None => view! { cx,
+            table(class="table") {
+                thead {
+                    tr {
+                        td { "Option" }
+                        (View::new_fragment(vref.iter().map(|e| view! {cx, td { (e.user_name) } }).collect()))
+                    }
+                }
+                tbody {
+                    (options)
+                }
+            }
+        },
+    };
+    let runoff = poll.result.as_ref().and_then(|r| r.runoff.clone());
+    let has_runoff = runoff.is_some();
+    let committee = poll.result.as_ref().and_then(|r| r.committee.clone());
+    let majority_judgment = poll.result.and_then(|r| {
+        let grades = r.majority_judgment?;
+        Some((r.ranking, grades))
+    });
     view! {
         cx,
         div {
+            (if has_runoff {
+                view! { cx, h4(class="title is-4") { "Scoring round" } }
+            } else {view! {cx, ""}})
+            (ballots)
+            (if let Some(runoff) = runoff.clone() {
+                view! { cx, ViewStarRunoff { options: poll.options.clone(), runoff } }
+            } else {view! {cx, ""}})
+            (if let Some(committee) = committee.clone() {
+                view! { cx, ViewCommittee { options: poll.options.clone(), voters: poll.votes.iter().map(|v| v.user_name.clone()).collect(), committee } }
+            } else {view! {cx, ""}})
+            (if let Some((ranking, grades)) = majority_judgment.clone() {
+                view! { cx, ViewMajorityJudgment { options: poll.options.clone(), ranking, grades } }
+            } else {view! {cx, ""}})
+        }
+    }
+}
+
+#[derive(Prop)]
+struct AggregatesProps {
+    options: Vec<PollOption>,
+    scale: ScoreScale,
+    aggregates: BallotAggregates,
+    /// name of the tally method and the result score of every option
+    result: Option<(String, HashMap<PollOptionId, Option<f64>>)>,
+}
+#[component]
+fn ViewAggregates<G: Html>(cx: Scope, props: AggregatesProps) -> View<G> {
+    let values = props.scale.values();
+    let score_headers = View::new_fragment(
+        values
+            .iter()
+            .map(|v| {
+                let v = format!("{v}");
+                view! { cx, td { (v) } }
+            })
+            .collect(),
+    );
+    let result_header = match &props.result {
+        Some((method, _)) => {
+            let header = format!("Result ({method})");
+            view! { cx, td { (header) } }
+        }
+        None => view! { cx, "" },
+    };
+    let rows = View::new_fragment(
+        props
+            .options
+            .iter()
+            .map(|o| {
+                let title = o.title.clone();
+                let aggregate = props
+                    .aggregates
+                    .options
+                    .get(&o.id)
+                    .cloned()
+                    .unwrap_or_default();
+                let mean = aggregate
+                    .mean
+                    .map(|m| format!("{:.1}", m))
+                    .unwrap_or_default();
+                let result = match &props.result {
+                    Some((_, scores)) => {
+                        let score = scores
+                            .get(&o.id)
+                            .copied()
+                            .flatten()
+                            .map(|s| format!("{:.1}", s))
+                            .unwrap_or_default();
+                        view! { cx, td { (score) } }
+                    }
+                    None => view! { cx, "" },
+                };
+                let distribution = View::new_fragment(
+                    aggregate
+                        .distribution
+                        .iter()
+                        .map(|n| {
+                            let n = *n;
+                            view! { cx, td { (n) } }
+                        })
+                        .collect(),
+                );
+                view! { cx,
+                    tr {
+                        td { (title) }
+                        td { (aggregate.count) }
+                        td { (aggregate.abstentions) }
+                        td { (mean) }
+                        (result)
+                        (distribution)
+                    }
+                }
+            })
+            .collect(),
+    );
+    view! { cx,
+        table(class="table") {
+            thead {
+                tr {
+                    td { "Option" }
+                    td { "Scores" }
+                    td { "Abstentions" }
+                    td { "Mean" }
+                    (result_header)
+                    (score_headers)
+                }
+            }
+            tbody {
+                (rows)
+            }
+        }
+    }
+}
+
+#[derive(Prop)]
+struct StarRunoffProps {
+    options: Vec<PollOption>,
+    runoff: StarRunoff,
+}
+#[component]
+fn ViewStarRunoff<G: Html>(cx: Scope, props: StarRunoffProps) -> View<G> {
+    let title_of = |id: &PollOptionId| {
+        props
+            .options
+            .iter()
+            .find(|o| &o.id == id)
+            .map(|o| o.title.clone())
+            .unwrap_or_default()
+    };
+    let winner = title_of(&props.runoff.winner);
+    let no_preference = props.runoff.no_preference;
+    let finalists = View::new_fragment(
+        props
+            .runoff
+            .finalists
+            .iter()
+            .map(|f| {
+                let title = title_of(&f.option);
+                let score_sum = format!("{:.1}", f.score_sum);
+                let preferred_by = f.preferred_by;
+                view! { cx,
+                    tr {
+                        td { (title) }
+                        td { (score_sum) }
+                        td { (preferred_by) }
+                    }
+                }
+            })
+            .collect(),
+    );
+    view! { cx,
+        div {
+            h4(class="title is-4") { "Automatic runoff" }
+            p { "The two options with the highest total score face each other. Each ballot counts for the finalist it scored higher." }
+            table(class="table") {
+                thead {
+                    tr { td { "Finalist" } td { "Total score" } td { "Preferred by" } }
+                }
+                tbody {
+                    (finalists)
+                    tr { td { "No preference" } td { "" } td { (no_preference) } }
+                }
+            }
+            div(class="notification is-success") { "Winner: " (winner) }
+        }
+    }
+}
+
+#[derive(Prop)]
+struct MajorityJudgmentProps {
+    options: Vec<PollOption>,
+    ranking: Vec<PollOptionId>,
+    grades: HashMap<PollOptionId, MajorityGrade>,
+}
+#[component]
+fn ViewMajorityJudgment<G: Html>(cx: Scope, props: MajorityJudgmentProps) -> View<G> {
+    let rows = View::new_fragment(
+        props
+            .ranking
+            .iter()
+            .filter_map(|id| {
+                let title = props.options.iter().find(|o| &o.id == id)?.title.clone();
+                let grade = props.grades.get(id)?;
+                let median = format!("{:.1}", grade.median);
+                let above = format!("{:.0}%", grade.above * 100.0);
+                let below = format!("{:.0}%", grade.below * 100.0);
+                let gauge = format!("{:+.0}%", grade.gauge() * 100.0);
+                Some(view! { cx,
+                    tr {
+                        td { (title) }
+                        td { (median) }
+                        td { (above) }
+                        td { (below) }
+                        td { (gauge) }
+                    }
+                })
+            })
+            .collect(),
+    );
+    view! { cx,
+        div {
+            h4(class="title is-4") { "Majority judgment" }
+            p { "Options are ranked by their median grade. Between options with the same median grade, the one with the higher majority gauge wins." }
             table(class="table") {
                 thead {
                     tr {
                         td { "Option" }
-                        (View::new_fragment(vref.iter().map(|e| view! {cx, td { (e.user_name) } }).collect()))
+                        td { "Median grade" }
+                        td { "Above median" }
+                        td { "Below median" }
+                        td { "Majority gauge" }
                     }
                 }
                 tbody {
-                    (options)
+                    (rows)
                 }
             }
         }
     }
 }
 
+#[derive(Prop)]
+struct CommitteeProps {
+    options: Vec<PollOption>,
+    /// names of the voters, in ballot order
+    voters: Vec<String>,
+    committee: CommitteeResult,
+}
+#[component]
+fn ViewCommittee<G: Html>(cx: Scope, props: CommitteeProps) -> View<G> {
+    let title_of = |id: &PollOptionId| {
+        props
+            .options
+            .iter()
+            .find(|o| &o.id == id)
+            .map(|o| o.title.clone())
+            .unwrap_or_default()
+    };
+    let method = props.committee.method.name();
+    let winners = View::new_fragment(
+        props
+            .committee
+            .rounds
+            .iter()
+            .map(|r| {
+                let title = title_of(&r.elected);
+                let score = format!("{:.2}", r.weighted_scores.get(&r.elected).unwrap_or(&0.0));
+                view! { cx, li { (title) " (weighted score " (score) ")" } }
+            })
+            .collect(),
+    );
+    let round_headers = View::new_fragment(
+        (1..=props.committee.rounds.len())
+            .map(|i| view! { cx, td { "Round " (i) } })
+            .collect(),
+    );
+    let weight_rows = View::new_fragment(
+        props
+            .voters
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let name = name.clone();
+                let weights = View::new_fragment(
+                    props
+                        .committee
+                        .rounds
+                        .iter()
+                        .map(|r| {
+                            let w = format!("{:.2}", r.ballot_weights.get(i).unwrap_or(&0.0));
+                            view! { cx, td { (w) } }
+                        })
+                        .collect(),
+                );
+                view! { cx,
+                    tr {
+                        td { (name) }
+                        (weights)
+                    }
+                }
+            })
+            .collect(),
+    );
+    view! { cx,
+        div {
+            h4(class="title is-4") { "Elected committee (" (method) ")" }
+            ol { (winners) }
+            h5(class="title is-5") { "Ballot weights per round" }
+            table(class="table") {
+                thead {
+                    tr {
+                        td { "Voter" }
+                        (round_headers)
+                    }
+                }
+                tbody {
+                    (weight_rows)
+                }
+            }
+        }
+    }
+}
+
+const POLLS_PER_PAGE: u32 = 20;
+
+/// loads the polls after `query.cursor` and appends them to `polls`. `latest_load` numbers the
+/// loads, a page that arrives after a newer load has started is dropped
+async fn load_poll_page(
+    api: SharedClient,
+    query: ListPolls,
+    polls: RcSignal<Vec<PollSummary>>,
+    next_cursor: RcSignal<Option<PublicPollId>>,
+    error: RcSignal<Option<String>>,
+    loading: RcSignal<bool>,
+    latest_load: RcSignal<u64>,
+) {
+    let first_page = query.cursor.is_none();
+    let this_load = *latest_load.get_untracked() + 1;
+    latest_load.set(this_load);
+    loading.set(true);
+    let page = api.call_idempotent(|c| c.list_polls(query.clone())).await;
+    if *latest_load.get_untracked() != this_load {
+        return;
+    }
+    loading.set(false);
+    match page {
+        Ok(page) => {
+            if first_page {
+                polls.set(page.polls);
+            } else {
+                polls.modify().extend(page.polls);
+            }
+            next_cursor.set(page.next_cursor);
+            error.set(None);
+        }
+        Err(e) => error.set(Some(format!("Could not load polls: {}", e))),
+    }
+}
+
+#[component]
+fn PollDirectory<G: Html>(cx: Scope) -> View<G> {
+    let title_filter = create_signal(cx, String::new());
+    let state_name = create_signal(cx, "open".to_string());
+    let query = create_memo(cx, || ListPolls {
+        cursor: None,
+        limit: POLLS_PER_PAGE,
+        title_contains: Some(title_filter.get().trim().to_string()).filter(|t| !t.is_empty()),
+        state: match state_name.get().as_str() {
+            "closed" => PollStateFilter::Closed,
+            "any" => PollStateFilter::Any,
+            _ => PollStateFilter::Open,
+        },
+    });
+    let polls = create_rc_signal(Vec::new());
+    let next_cursor = create_rc_signal(None);
+    let error = create_rc_signal(None);
+    let polls_ref = create_ref(cx, polls.clone());
+    let next_cursor_ref = create_ref(cx, next_cursor.clone());
+    let error_ref = create_ref(cx, error.clone());
+    let loading = create_rc_signal(false);
+    let loading_ref = create_ref(cx, loading.clone());
+    let latest_load = create_rc_signal(0);
+    let latest_load_ref = create_ref(cx, latest_load.clone());
+    let api = create_ref(cx, use_context::<SharedClient>(cx).clone());
+
+    create_effect(cx, move || {
+        wasm_bindgen_futures::spawn_local(load_poll_page(
+            api.clone(),
+            (*query.get()).clone(),
+            polls_ref.clone(),
+            next_cursor_ref.clone(),
+            error_ref.clone(),
+            loading_ref.clone(),
+            latest_load_ref.clone(),
+        ));
+    });
+    let load_more = move |_| {
+        let query = ListPolls {
+            cursor: (*next_cursor.get()).clone(),
+            ..(*query.get()).clone()
+        };
+        wasm_bindgen_futures::spawn_local(load_poll_page(
+            api.clone(),
+            query,
+            polls.clone(),
+            next_cursor.clone(),
+            error.clone(),
+            loading.clone(),
+            latest_load.clone(),
+        ));
+    };
+
+    view! { cx,
+        div {
+            h2(class="title is-2") { "Polls" }
+            div(class="field is-grouped") {
+                div(class="control is-expanded") {
+                    input(class="input", placeholder="Search by title", bind:value=title_filter)
+                }
+                div(class="control") {
+                    div(class="select") {
+                        select(bind:value=state_name) {
+                            option(value="open") { "Open" }
+                            option(value="closed") { "Closed" }
+                            option(value="any") { "All" }
+                        }
+                    }
+                }
+            }
+            (if let Some(e) = (*error_ref.get()).clone() {
+                view! { cx, div(class="notification is-danger") { (e) } }
+            } else {view! {cx, ""}})
+            table(class="table") {
+                thead {
+                    tr { td { "Title" } td { "Ballots" } td { "State" } }
+                }
+                tbody {
+                    (View::new_fragment(polls_ref.get().iter().map(|p| {
+                        let link = format!("/poll/{}", p.id.to_str());
+                        let title = p.title.clone();
+                        let ballots = p.ballots;
+                        let state = if p.closed { "Closed" } else { "Open" };
+                        view! { cx,
+                            tr {
+                                td { a(href=link) { (title) } }
+                                td { (ballots) }
+                                td { (state) }
+                            }
+                        }
+                    }).collect()))
+                }
+            }
+            (if next_cursor_ref.get().is_some() {
+                view! { cx, button(class=button_class("button", *loading_ref.get()), on:click=load_more) { "Load more" } }
+            } else {view! {cx, ""}})
+        }
+    }
+}
+
 fn switch<'a, G: Html>(cx: Scope<'a>, route: &'a ReadSignal<AppRoutes>) -> View<G> {
     view! { cx,
         div { (match route.get().as_ref() {
             AppRoutes::ViewPoll { poll_id } => view! { cx,
                 LoadViewPoll(poll_id.to_string())
             },
+            AppRoutes::ViewPairwise { poll_id } => view! { cx,
+                LoadViewPairwise(poll_id.to_string())
+            },
+            AppRoutes::AdminPoll { poll_id, secret } => view! { cx,
+                LoadAdminPoll { poll_id: poll_id.to_string(), secret: secret.to_string() }
+            },
+            AppRoutes::PollDirectory => view! { cx, PollDirectory() },
             AppRoutes::CreatePollFonk => view! { cx, CreatePoll() },
             AppRoutes::NotFound => view! { cx, "404 Not Found" },
         }) }
     }
 }
 
+#[component]
+/// shown above every page while the server can't be reached, and while requests are running
+fn ConnectionBanner<G: Html>(cx: Scope) -> View<G> {
+    let api = use_context::<SharedClient>(cx);
+    let problem = create_ref(cx, api.connection_problem.clone());
+    let pending = create_ref(cx, api.pending.clone());
+    let retry = |_| {
+        if let Some(window) = web_sys::window() {
+            let _ = window.location().reload();
+        }
+    };
+    view! { cx,
+        (if let Some(e) = (*problem.get()).clone() {
+            view! { cx,
+                div(class="notification is-warning") {
+                    p { "Could not reach the server: " (e) }
+                    button(class="button is-small mt-2", on:click=retry) { "Retry" }
+                }
+            }
+        } else {view! {cx, ""}})
+        (if *pending.get() > 0 {
+            view! { cx, progress(class="progress is-small is-info") {} }
+        } else {view! {cx, ""}})
+    }
+}
+
 #[component]
 fn App<G: Html>(cx: Scope) -> View<G> {
+    provide_context(cx, SharedClient::new());
     view! { cx,
         section(class="section") {
             div(class="container") {
                 h1(class="title is-1") { "Score Voting Tool" }
+                div(class="buttons") {
+                    a(class="button is-small", href="/") { "New poll" }
+                    a(class="button is-small", href="/polls") { "All polls" }
+                }
+                ConnectionBanner {}
                 Router {
                     integration: HistoryIntegration::new(),
                     view: switch,
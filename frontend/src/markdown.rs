@@ -0,0 +1,23 @@
+//! rendering of the markdown in poll and option descriptions
+use pulldown_cmark::{html, Options, Parser};
+use sycamore::prelude::*;
+
+/// HTML for `markdown`. descriptions are written by whoever creates a poll, so raw HTML in
+/// them is sanitized before it reaches the page of other voters
+pub fn render(markdown: &str) -> String {
+    let parser = Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES,
+    );
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+    ammonia::clean(&unsafe_html)
+}
+
+#[component]
+pub fn Markdown<G: Html>(cx: Scope, markdown: String) -> View<G> {
+    let html = render(&markdown);
+    view! { cx,
+        div(class="content", dangerously_set_inner_html=html)
+    }
+}